@@ -0,0 +1,4964 @@
+// rs-es-dashview's ingestion core: the state documents are recorded in, the mapping of their
+// columns to rows, and the HTTP server that receives them. The terminal UI lives in the binary
+// and drives this state; other programs can embed it with start_server and read it back.
+// The types only the UI needs, its theme, actions and key bindings, are left out without the tui feature.
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use flate2::read::GzDecoder;
+use futures_util::{future, SinkExt, Stream, StreamExt};
+#[cfg(feature = "tui")]
+use ratatui::{
+    crossterm::event::KeyCode,
+    style::{Color, Style, Stylize},
+};
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Serialize,
+};
+use std::{
+    borrow::Cow,
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    net::SocketAddrV4,
+    ops::Bound,
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        mpsc::SyncSender,
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+use reqwest::Url;
+use subtle::ConstantTimeEq;
+use tokio::{net::UnixListener, sync::broadcast, time::MissedTickBehavior};
+use tokio_stream::wrappers::UnixListenerStream;
+use uuid::Uuid;
+use warp::{
+    http::StatusCode,
+    hyper::body::Buf,
+    ws::{Message, WebSocket},
+    Filter, Reply,
+};
+
+pub const ADDRESS: [u8; 4] = [127, 0, 0, 1];
+pub const PORT: u16 = 33433;
+
+const TIMESTAMP: &str = "@timestamp";
+const AGENT_ID: &str = "agent.id";
+const HOST_NAME: &str = "host.name";
+const HOST_OS_NAME: &str = "host.os.name";
+const USER_NAME: &str = "user.name";
+const HOST_IP: &str = "host.ip";
+
+pub const HISTORY_CAPACITY: usize = 100;
+
+pub const DEFAULT_CHANNEL: &str = "default";
+const SEARCH_PATH: &str = "search"; // Under "/data", where _search responses are posted instead of a channel's name
+
+const MAX_COLUMN_WIDTH: u16 = 40;
+
+pub const SERIALIZATION_ERROR: &str = "<serialization error>";
+pub const MISSING: &str = "<missing>";
+pub const REDACTED: &str = "****";
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Column types Elasticsearch reports for numbers, which are right-aligned in the table
+pub const NUMERIC_TYPES: [&str; 9] = [
+    "long",
+    "integer",
+    "short",
+    "byte",
+    "unsigned_long",
+    "double",
+    "float",
+    "half_float",
+    "scaled_float",
+];
+
+// Numeric column types whose values are fractional, typed as floats even when a value happens to be whole
+const FLOAT_TYPES: [&str; 4] = ["double", "float", "half_float", "scaled_float"];
+
+// Epoch numbers below this are taken as seconds and the rest as milliseconds; 1e11 seconds is in the year 5138,
+// while 1e11 milliseconds is in 1973, so real timestamps of either kind fall on the right side
+const EPOCH_SECONDS_LIMIT: f64 = 1e11;
+
+pub const CONFIG_PATH: &str = "dashview.json";
+pub const STATE_PATH: &str = "dashview-state.json";
+const FEED_TEMPLATE: &str = "{@timestamp} {host.name} {user.name}";
+
+pub const REFRESH_MS: u64 = 2500;
+pub const DEBOUNCE_MS: u64 = 250;
+pub const STALE_SECS: u64 = 60;
+pub const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(2);
+const OUTPUT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const HISTORY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+pub const ES_INTERVAL_SECS: u64 = 10;
+const STREAM_CAPACITY: usize = 64;
+const HISTOGRAM_BUCKETS: usize = 10;
+pub const HISTOGRAM_MAX_BUCKETS: usize = 50;
+pub const RECENT_TABS: usize = 5;
+pub const MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+const TOOK_AVERAGE_WEIGHT: f64 = 0.1;
+pub type JsonValue = serde_json::Value;
+pub type JsonMap = HashMap<String, JsonValue>;
+pub type SharedAppState = Arc<RwLock<AppState>>;
+pub type ShutdownFlag = Arc<AtomicBool>;
+#[derive(Debug)]
+pub enum Listen {
+    Tcp(SocketAddrV4),                   // Listen on a TCP address
+    Tls(SocketAddrV4, PathBuf, PathBuf), // Listen on a TCP address over HTTPS with this certificate and key
+    Unix(UnixListener), // Accept connections on an already bound Unix domain socket
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub fields: Vec<String>, // Keys shown in the fields pane, in display order
+    pub field_order: FieldOrder, // Whether the fields pane follows `fields` or the document's columns
+    pub max_column_width: u16,   // Widest a table column grows before its cells are truncated
+    pub alerts: Vec<AlertRule>,  // Rules that raise an alert when a received document matches one
+    pub polls: Vec<PollSpec>, // ES SQL queries polled from --es-url, each into a channel of its own
+    pub bell: bool,           // Whether raising an alert also rings the terminal bell
+    pub aliases: HashMap<String, String>, // Labels shown in place of raw field names
+    pub feed_template: String, // Line each document collapses to in the feed view, with {field} placeholders
+    #[cfg(feature = "tui")]
+    pub keybindings: HashMap<Action, KeySpecs>, // Keys replacing the defaults of the actions listed
+    pub hidden_columns: HashSet<String>, // Columns hidden from the table, fields pane and inspector at startup
+    #[cfg(feature = "tui")]
+    #[serde(skip_serializing)]
+    pub theme: Option<Theme>, // Colours of the dashboard, a preset's name or a preset with some colours replaced
+    pub numbers: NumberFormat, // How numbers in numeric columns of the table are written
+    pub title: Option<String>, // Banner shown above the dashboard, with {channel} and {count} placeholders
+    pub symbols: HashMap<String, Symbol>, // Symbols boolean values are drawn as, by field name or column type
+    pub redact: HashSet<String>, // Fields whose values are masked on screen and in POST responses
+    pub redact_mode: RedactMode, // Whether redacted values are still kept for history and exports
+}
+
+// What happens to the values of redacted fields as documents arrive
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactMode {
+    #[default]
+    Mask, // Keep the raw value for history, output and exports, masking it wherever it is shown
+    Drop, // Replace the value with null before it is stored anywhere
+}
+
+// Symbols a boolean table cell can be drawn as instead of yes / no
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbol {
+    Check, // A green tick for true and a red cross for false
+    Dot,   // A green dot for true and a red one for false
+}
+
+#[cfg(feature = "tui")]
+impl Symbol {
+    // The symbol for a value and its colour, spelt in ASCII as y / n or * when ascii is set
+    pub fn render(self, flag: bool, ascii: bool) -> (&'static str, Style) {
+        let color = if flag { Color::Green } else { Color::Red };
+        let symbol = match (self, flag, ascii) {
+            (Self::Check, true, false) => "✓",
+            (Self::Check, false, false) => "✗",
+            (Self::Check, true, true) => "y",
+            (Self::Check, false, true) => "n",
+            (Self::Dot, _, false) => "●",
+            (Self::Dot, _, true) => "*",
+        };
+        (symbol, Style::new().fg(color))
+    }
+}
+
+// How numbers in numeric columns of the table are displayed. Exports and the HTTP endpoints keep the raw values.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct NumberFormat {
+    thousands: bool, // Whether to group the digits before the decimal point in threes with commas
+    precision: Option<usize>, // Decimal places floats are rounded to, None to write them as they are
+}
+
+impl NumberFormat {
+    // Write an integer, grouping its digits when enabled
+    fn integer(&self, number: i64) -> String {
+        self.grouped(number.to_string())
+    }
+
+    // Write a float at the configured precision, grouping its digits when enabled
+    fn float(&self, number: f64) -> String {
+        let text = match self.precision {
+            Some(precision) => format!("{number:.precision$}"),
+            None => number.to_string(),
+        };
+        self.grouped(text)
+    }
+
+    // Put a separator between every three digits of the integer part of a written number,
+    // leaving the sign and anything from the decimal point or exponent onwards as it is
+    fn grouped(&self, text: String) -> String {
+        if !self.thousands {
+            return text;
+        }
+        let (sign, unsigned) = text.split_at(usize::from(text.starts_with('-')));
+        let end = unsigned
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(unsigned.len());
+        let (digits, rest) = unsigned.split_at(end);
+
+        let mut grouped = String::from(sign);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped.push_str(rest);
+        grouped
+    }
+}
+
+// Colours of the dashboard. Unset colours keep the terminal's own, with the selected row and
+// the status bar drawn in reversed colours. The default preset only uses named colours, which terminals
+// adapt to their palette.
+#[cfg(feature = "tui")]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "ThemeSpec")]
+pub struct Theme {
+    header: Option<Color>,   // Text of the table header
+    selected: Option<Color>, // Background of the selected row, search result and tab
+    status: Option<Color>,   // Background of the status bar
+    alert: Option<Color>,    // Background of the status bar while an alert flashes
+    string: Option<Color>,   // String values
+    number: Option<Color>,   // Number values
+    boolean: Option<Color>,  // Boolean values
+}
+
+// A theme as --persist-state saves it, every colour written out and an unset one as null, so it reads
+// back exactly rather than through a preset
+#[cfg(feature = "tui")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Theme")]
+struct ThemeDef {
+    header: Option<Color>,
+    selected: Option<Color>,
+    status: Option<Color>,
+    alert: Option<Color>,
+    string: Option<Color>,
+    number: Option<Color>,
+    boolean: Option<Color>,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Serialize, Deserialize, Debug)]
+struct SavedTheme(#[serde(with = "ThemeDef")] Theme);
+
+// Themes selectable by name in the config file, the first being the default
+#[cfg(feature = "tui")]
+const THEME_PRESETS: [(&str, Theme); 4] = [
+    (
+        "default",
+        Theme {
+            header: None,
+            selected: None,
+            status: None,
+            alert: Some(Color::Red),
+            string: Some(Color::Green),
+            number: Some(Color::Cyan),
+            boolean: None,
+        },
+    ),
+    (
+        "dark",
+        Theme {
+            header: Some(Color::LightCyan),
+            selected: Some(Color::DarkGray),
+            status: Some(Color::Blue),
+            alert: Some(Color::Red),
+            string: Some(Color::LightGreen),
+            number: Some(Color::LightYellow),
+            boolean: Some(Color::LightMagenta),
+        },
+    ),
+    (
+        "light",
+        Theme {
+            header: Some(Color::Blue),
+            selected: Some(Color::Gray),
+            status: Some(Color::Gray),
+            alert: Some(Color::LightRed),
+            string: Some(Color::Green),
+            number: Some(Color::Blue),
+            boolean: Some(Color::Magenta),
+        },
+    ),
+    (
+        "solarized",
+        Theme {
+            header: Some(Color::Rgb(0x26, 0x8b, 0xd2)),
+            selected: Some(Color::Rgb(0x07, 0x36, 0x42)),
+            status: Some(Color::Rgb(0x07, 0x36, 0x42)),
+            alert: Some(Color::Rgb(0xdc, 0x32, 0x2f)),
+            string: Some(Color::Rgb(0x85, 0x99, 0x00)),
+            number: Some(Color::Rgb(0x2a, 0xa1, 0x98)),
+            boolean: Some(Color::Rgb(0xd3, 0x36, 0x82)),
+        },
+    ),
+];
+
+#[cfg(feature = "tui")]
+impl Default for Theme {
+    fn default() -> Self {
+        THEME_PRESETS[0].1
+    }
+}
+
+#[cfg(feature = "tui")]
+impl Theme {
+    // Style of the table header
+    pub fn header_style(&self) -> Style {
+        let style = Style::new().bold();
+        self.header.map_or(style, |color| style.fg(color))
+    }
+
+    // Style of the highlighted entry of a table, list or tab bar
+    pub fn selected_style(&self) -> Style {
+        self.selected
+            .map_or_else(|| Style::new().reversed(), |color| Style::new().bg(color))
+    }
+
+    // Style of the status bar, in the alert colour while a raised alert flashes
+    pub fn status_style(&self, flash: bool) -> Style {
+        match (flash, self.alert, self.status) {
+            (true, Some(color), _) | (false, _, Some(color)) => Style::new().bg(color),
+            _ => Style::new().reversed(),
+        }
+    }
+
+    // Colour for a value by its JSON type, with nulls in italics and objects and arrays in the default foreground
+    pub fn value_style(&self, value: &JsonValue) -> Style {
+        let color = match value {
+            JsonValue::String(_) => self.string,
+            JsonValue::Number(_) => self.number,
+            JsonValue::Bool(_) => self.boolean,
+            JsonValue::Null => return Style::new().italic(),
+            _ => None,
+        };
+        color.map_or_else(Style::new, |color| Style::new().fg(color))
+    }
+
+    // Colour for a typed cell, matching value_style for the JSON it came from
+    pub fn cell_style(&self, cell: &TypedCell) -> Style {
+        let color = match cell {
+            TypedCell::Str(_) => self.string,
+            TypedCell::Int(_) | TypedCell::Float(_) => self.number,
+            TypedCell::Bool(_) => self.boolean,
+            TypedCell::Null => return Style::new().italic(),
+            TypedCell::Json(_) => None,
+        };
+        color.map_or_else(Style::new, |color| Style::new().fg(color))
+    }
+}
+
+// A theme as written in the config file: a preset's name, or an object naming the preset to start from
+// and the colours to replace, each a colour name like "red" or "lightblue" or a hex "#rrggbb"
+#[cfg(feature = "tui")]
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ThemeSpec {
+    Preset(String),
+    Custom(ThemeColors),
+}
+
+#[cfg(feature = "tui")]
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeColors {
+    preset: Option<String>, // Preset the colours start from, "default" when unset
+    header: Option<String>,
+    selected: Option<String>,
+    status: Option<String>,
+    alert: Option<String>,
+    string: Option<String>,
+    number: Option<String>,
+    boolean: Option<String>,
+}
+
+#[cfg(feature = "tui")]
+impl TryFrom<ThemeSpec> for Theme {
+    type Error = String;
+
+    fn try_from(spec: ThemeSpec) -> Result<Self, Self::Error> {
+        let colors = match spec {
+            ThemeSpec::Preset(name) => ThemeColors {
+                preset: Some(name),
+                ..ThemeColors::default()
+            },
+            ThemeSpec::Custom(colors) => colors,
+        };
+        let name = colors.preset.as_deref().unwrap_or(THEME_PRESETS[0].0);
+        let Some(&(_, mut theme)) = THEME_PRESETS.iter().find(|(preset, _)| *preset == name) else {
+            return Err(format!("unknown theme preset {name:?}"));
+        };
+
+        let parse = |spec: &Option<String>, color: &mut Option<Color>| match spec {
+            Some(spec) => match spec.parse() {
+                Ok(parsed) => {
+                    *color = Some(parsed);
+                    Ok(())
+                }
+                Err(_) => Err(format!("unknown theme colour {spec:?}")),
+            },
+            None => Ok(()),
+        };
+        parse(&colors.header, &mut theme.header)?;
+        parse(&colors.selected, &mut theme.selected)?;
+        parse(&colors.status, &mut theme.status)?;
+        parse(&colors.alert, &mut theme.alert)?;
+        parse(&colors.string, &mut theme.string)?;
+        parse(&colors.number, &mut theme.number)?;
+        parse(&colors.boolean, &mut theme.boolean)?;
+        Ok(theme)
+    }
+}
+
+// Keys bound to an action in the config file, either a single key or a list of them
+#[cfg(feature = "tui")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum KeySpecs {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[cfg(feature = "tui")]
+impl KeySpecs {
+    fn specs(&self) -> &[String] {
+        match self {
+            KeySpecs::One(spec) => std::slice::from_ref(spec),
+            KeySpecs::Many(specs) => specs,
+        }
+    }
+}
+
+// A query polled from the cluster on its own schedule, its results going to the channel of the same name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PollSpec {
+    pub name: String,          // Channel the results are shown on
+    pub query: String,         // ES SQL query sent on every poll
+    pub interval: Option<u64>, // Seconds between polls, --es-interval when left out
+}
+
+// An alert rule matches a document when any of its rows has the field set to exactly the given value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub field: String,     // Column the rule looks at
+    pub equals: JsonValue, // Value that raises the alert, compared as JSON so "500" and 500 differ
+}
+
+impl AlertRule {
+    fn matches(&self, log: &Log) -> bool {
+        let Some(index) = log
+            .columns
+            .iter()
+            .position(|column| column.name == self.field)
+        else {
+            return false;
+        };
+        log.values
+            .iter()
+            .any(|row| row.get(index) == Some(&self.equals))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub rule: AlertRule, // Rule the document matched
+    pub raised: Instant, // When the alert was raised, which the status bar flashes relative to
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldOrder {
+    #[default]
+    Fixed, // Show the configured fields in the configured order
+    AsReceived, // Show every column of the document in the order Elasticsearch returned them
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fields: [
+                TIMESTAMP,
+                AGENT_ID,
+                HOST_NAME,
+                HOST_OS_NAME,
+                USER_NAME,
+                HOST_IP,
+            ]
+            .map(String::from)
+            .to_vec(),
+            field_order: FieldOrder::Fixed,
+            max_column_width: MAX_COLUMN_WIDTH,
+            alerts: vec![],
+            polls: vec![],
+            bell: false,
+            aliases: HashMap::new(),
+            feed_template: FEED_TEMPLATE.to_string(),
+            #[cfg(feature = "tui")]
+            keybindings: HashMap::new(),
+            hidden_columns: HashSet::new(),
+            #[cfg(feature = "tui")]
+            theme: None,
+            numbers: NumberFormat::default(),
+            title: None,
+            symbols: HashMap::new(),
+            redact: HashSet::new(),
+            redact_mode: RedactMode::default(),
+        }
+    }
+}
+
+impl Config {
+    // Read the config file, falling back to the defaults when it does not exist
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// The parts of the dashboard --persist-state saves on exit and restores on the next start
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct UiState {
+    hidden_columns: BTreeSet<String>, // Columns turned off in the column picker
+    filter: String,                   // Text a row must contain to be shown
+    sort: Option<(usize, SortDirection)>, // Column the rows are ordered by, None for insertion order
+    channel: Option<String>,              // Channel on screen, None for the default one
+    #[cfg(feature = "tui")]
+    theme: Option<SavedTheme>, // Colours in use, which a theme set in the config file still overrides
+}
+
+impl UiState {
+    // Take the parts worth keeping from the dashboard
+    pub fn capture(state: &AppState) -> Self {
+        Self {
+            hidden_columns: state.hidden_columns.iter().cloned().collect(),
+            filter: state.filter.clone(),
+            sort: state.sort,
+            channel: (state.channel != DEFAULT_CHANNEL).then(|| state.channel.clone()),
+            #[cfg(feature = "tui")]
+            theme: Some(SavedTheme(state.theme)),
+        }
+    }
+
+    // Put the saved parts back on a dashboard that has not received anything yet
+    pub fn restore(self, state: &mut AppState) {
+        state.hidden_columns = self.hidden_columns.into_iter().collect();
+        state.filter = self.filter;
+        state.sort = self.sort;
+        if let Some(channel) = self.channel {
+            state.channel = channel;
+        }
+        #[cfg(feature = "tui")]
+        if let Some(SavedTheme(theme)) = self.theme {
+            state.theme = theme;
+        }
+    }
+
+    // Read the state file, giving None when it is missing and logging why when it cannot be used
+    pub fn load(path: &Path) -> Option<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "failed to read state file");
+                return None;
+            }
+        };
+        serde_json::from_str(&text)
+            .inspect_err(|e| {
+                tracing::warn!(path = %path.display(), error = %e, "ignoring corrupt state file")
+            })
+            .ok()
+    }
+
+    // Write the state file, replacing any earlier one
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, text)
+    }
+}
+
+// Only the rows are required, so ES|QL responses ("values") and ES SQL responses ("rows") both parse,
+// as do follow-up SQL pages, which carry no columns or timing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Log {
+    #[serde(alias = "rows")]
+    pub values: Vec<Vec<JsonValue>>, // A 2D vector holding the log values
+    #[serde(default)]
+    pub took: u32, // Time taken to process the log, 0 when not reported
+    #[serde(default)]
+    pub columns: Vec<Column>, // Metadata about the columns in the log, empty when not sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>, // Pagination cursor of an ES SQL response with more pages
+    #[serde(skip)]
+    timestamps: BTreeMap<usize, JsonValue>, // Canonical @timestamp by row, for the rows normalize_timestamps found another form in
+    #[serde(skip)]
+    pub cells: Vec<Vec<TypedCell>>, // The values typed by their column, filled in by type_cells when the log is received
+    #[serde(skip)]
+    pub received: Option<Instant>, // When the log arrived, for --history-ttl, None for one that was never received
+}
+
+impl Log {
+    fn new() -> Self {
+        Self {
+            values: vec![vec![]],
+            took: 0,
+            columns: vec![],
+            cursor: None,
+            timestamps: BTreeMap::new(),
+            cells: vec![vec![]],
+            received: None,
+        }
+    }
+
+    // Work out the canonical RFC 3339 UTC form of every @timestamp that parses, with millisecond precision or
+    // nanoseconds in a date_nanos column, so they sort and display alike. The values and columns stay as received,
+    // for the replies, history and output; the cells and mapped rows read the canonical forms through value and
+    // column_type instead. Values that do not parse are left for type_warnings to flag.
+    fn normalize_timestamps(&mut self) {
+        self.timestamps.clear();
+        let Some(index) = self.timestamp_column() else {
+            return;
+        };
+        let precision = match self.columns[index].column_type.as_str() {
+            "date_nanos" => SecondsFormat::Nanos,
+            _ => SecondsFormat::Millis,
+        };
+
+        for (row, values) in self.values.iter().enumerate() {
+            let Some(value) = values.get(index) else {
+                continue;
+            };
+            let Some(timestamp) = parse_timestamp(value) else {
+                continue;
+            };
+            let canonical = JsonValue::String(timestamp.to_rfc3339_opts(precision, true));
+            if *value != canonical {
+                self.timestamps.insert(row, canonical);
+            }
+        }
+    }
+
+    // Position of the @timestamp column, None when the document has none
+    fn timestamp_column(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == TIMESTAMP)
+    }
+
+    // The value at a row and column as the cells and mapped rows see it: an @timestamp in its canonical form,
+    // anything else as received. None when the row is too short to have it.
+    pub fn value(&self, row: usize, column: usize) -> Option<&JsonValue> {
+        match self.timestamps.get(&row) {
+            Some(canonical) if self.timestamp_column() == Some(column) => Some(canonical),
+            _ => self.values.get(row)?.get(column),
+        }
+    }
+
+    // A column's type as the cells see it, where an epoch number @timestamp column holds dates once normalized
+    pub fn column_type(&self, column: usize) -> &str {
+        let column_type = self
+            .columns
+            .get(column)
+            .map_or("", |c| c.column_type.as_str());
+        if !self.timestamps.is_empty()
+            && self.timestamp_column() == Some(column)
+            && NUMERIC_TYPES.contains(&column_type)
+        {
+            "date"
+        } else {
+            column_type
+        }
+    }
+
+    // The @timestamp values as received, by row, for the rows whose canonical form differs from them
+    pub fn original_timestamps(&self) -> BTreeMap<usize, &JsonValue> {
+        let Some(index) = self.timestamp_column() else {
+            return BTreeMap::new();
+        };
+        (self.timestamps.keys())
+            .filter_map(|&row| Some((row, self.values.get(row)?.get(index)?)))
+            .collect()
+    }
+
+    // Type every value by its column's declared type, for the table, sorting and export
+    fn type_cells(&mut self) {
+        self.cells = (0..self.values.len())
+            .map(|row| {
+                (0..self.values[row].len())
+                    .filter_map(|i| Some(TypedCell::new(self.value(row, i)?, self.column_type(i))))
+                    .collect()
+            })
+            .collect();
+    }
+
+    // The typed value at a row and column, None when the row is too short to have it
+    pub fn cell(&self, row: usize, column: usize) -> Option<&TypedCell> {
+        self.cells.get(row).and_then(|cells| cells.get(column))
+    }
+
+    // The document with every value of the given fields replaced, borrowed as it is when it has none of them
+    fn replaced(&self, fields: &HashSet<String>, replacement: JsonValue) -> Cow<'_, Self> {
+        let indices: Vec<usize> = (self.columns.iter().enumerate())
+            .filter(|(_, column)| fields.contains(&column.name))
+            .map(|(i, _)| i)
+            .collect();
+        if indices.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let mut log = self.clone();
+        if log.timestamp_column().is_some_and(|i| indices.contains(&i)) {
+            log.timestamps.clear();
+        }
+        for row in &mut log.values {
+            for &i in &indices {
+                if let Some(value) = row.get_mut(i) {
+                    *value = replacement.clone();
+                }
+            }
+        }
+        log.type_cells();
+        Cow::Owned(log)
+    }
+
+    // Hash of the document's values, or only of the key column's when it has one, for spotting repeats
+    fn fingerprint(&self, key: Option<&str>) -> u64 {
+        let index = key.and_then(|key| self.columns.iter().position(|column| column.name == key));
+        let json = match index {
+            Some(i) => {
+                serde_json::to_vec(&self.values.iter().map(|row| row.get(i)).collect::<Vec<_>>())
+            }
+            None => serde_json::to_vec(&self.values),
+        };
+        // FNV-1a, which is quicker than SipHash on every document and fine for telling repeats apart
+        json.unwrap_or_default()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    // The document with the values of the given fields masked, for showing it to someone
+    fn redacted(&self, fields: &HashSet<String>) -> Cow<'_, Self> {
+        self.replaced(fields, JsonValue::from(REDACTED))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Column {
+    pub name: String, // Name of the column
+    #[serde(rename = "type")]
+    pub column_type: String, // Type of the column, renamed to "type" in JSON
+}
+
+// An Elasticsearch _search response, as posted to "/data/search". Only the hits are required.
+#[derive(Deserialize, Debug)]
+struct HitsResponse {
+    #[serde(default)]
+    took: u32, // Time the search took, 0 when not reported
+    hits: Hits, // The matching documents
+}
+
+#[derive(Deserialize, Debug)]
+struct Hits {
+    hits: Vec<Hit>, // One entry per matching document
+}
+
+#[derive(Deserialize, Debug)]
+struct Hit {
+    #[serde(rename = "_source", default)]
+    source: serde_json::Map<String, JsonValue>, // The document as it was indexed, empty when the search left it out
+}
+
+// The hits of a search become the rows of a document. Nested objects in each _source are flattened into
+// dotted columns such as host.name, columns are the union of every hit's fields in the order they are first
+// seen, and a hit without one of them gets a null. Column types are guessed from the first value that is not null.
+impl From<HitsResponse> for Log {
+    fn from(response: HitsResponse) -> Self {
+        let mut columns: Vec<Column> = vec![];
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut rows = vec![];
+        for hit in response.hits.hits {
+            let mut fields = vec![];
+            flatten_source(String::new(), hit.source, &mut fields);
+            let mut row = vec![JsonValue::Null; columns.len()];
+            for (name, value) in fields {
+                let index = *positions.entry(name.clone()).or_insert_with(|| {
+                    columns.push(Column {
+                        name,
+                        column_type: String::from("null"),
+                    });
+                    columns.len() - 1
+                });
+                if columns[index].column_type == "null" {
+                    columns[index].column_type = json_type(&value).to_string();
+                }
+                row.resize(columns.len(), JsonValue::Null);
+                row[index] = value;
+            }
+            rows.push(row);
+        }
+        for row in &mut rows {
+            row.resize(columns.len(), JsonValue::Null);
+        }
+
+        Self {
+            values: rows,
+            took: response.took,
+            columns,
+            cursor: None,
+            timestamps: BTreeMap::new(),
+            cells: vec![],
+            received: None,
+        }
+    }
+}
+
+// Collect the fields of a _source object under their dotted names, descending into nested objects.
+// Arrays and empty objects are kept whole as the value of their field.
+
+fn flatten_source(
+    prefix: String,
+    source: serde_json::Map<String, JsonValue>,
+    fields: &mut Vec<(String, JsonValue)>,
+) {
+    for (key, value) in source {
+        let name = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            JsonValue::Object(object) if !object.is_empty() => flatten_source(name, object, fields),
+            value => fields.push((name, value)),
+        }
+    }
+}
+
+// The Elasticsearch type a JSON value would most likely have been mapped to, judging by the value alone.
+// Arrays take the type of their first element, and nulls stay "null" until a real value turns up.
+
+fn json_type(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(number) if number.is_f64() => "double",
+        JsonValue::Number(_) => "long",
+        JsonValue::String(_) => "keyword",
+        JsonValue::Array(values) => values.first().map_or("null", json_type),
+        JsonValue::Object(_) => "object",
+    }
+}
+
+// A value typed by its column, so numbers compare and print as numbers rather than JSON text.
+// Numbers in float columns are always floats, numeric and boolean columns also read their values from strings,
+// and anything that fits no other variant is kept as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedCell {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+    Json(JsonValue),
+}
+
+impl TypedCell {
+    fn new(value: &JsonValue, column_type: &str) -> Self {
+        let float = FLOAT_TYPES.contains(&column_type);
+        let numeric = NUMERIC_TYPES.contains(&column_type);
+        let typed = match value {
+            JsonValue::Null => Some(Self::Null),
+            JsonValue::Bool(flag) => Some(Self::Bool(*flag)),
+            JsonValue::Number(number) if float => number.as_f64().map(Self::Float),
+            JsonValue::Number(number) => number
+                .as_i64()
+                .map(Self::Int)
+                .or_else(|| number.as_f64().map(Self::Float)),
+            JsonValue::String(text) if float => text.parse().ok().map(Self::Float),
+            JsonValue::String(text) if numeric => text.parse().ok().map(Self::Int),
+            JsonValue::String(text) if column_type == "boolean" => {
+                text.parse().ok().map(Self::Bool)
+            }
+            JsonValue::String(text) => Some(Self::Str(text.clone())),
+            _ => None,
+        };
+        typed.unwrap_or_else(|| Self::Json(value.clone()))
+    }
+
+    // The value as a number, None for anything else
+    pub fn number(&self) -> Option<f64> {
+        match self {
+            Self::Int(number) => Some(*number as f64),
+            Self::Float(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    // Order two cells: numbers first in numeric order, comparing integers exactly, then everything else by its text
+    fn compare(&self, other: &Self) -> cmp::Ordering {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.cmp(b),
+            _ => match (self.number(), other.number()) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => cmp::Ordering::Less,
+                (None, Some(_)) => cmp::Ordering::Greater,
+                (None, None) => self.plain_text().cmp(&other.plain_text()),
+            },
+        }
+    }
+
+    // The human-friendly form of the cell when its column type has one: dates become readable UTC timestamps
+    // and booleans become yes/no. None when the type has no special format or the value does not fit it.
+    fn formatted(&self, column_type: &str) -> Option<String> {
+        match (column_type, self) {
+            ("date" | "date_nanos", Self::Str(text)) => {
+                parse_timestamp_text(text).map(format_timestamp)
+            }
+            ("date" | "date_nanos", Self::Int(epoch)) => {
+                parse_epoch(*epoch as f64).map(format_timestamp)
+            }
+            ("boolean", Self::Bool(flag)) => Some(String::from(if *flag { "yes" } else { "no" })),
+            _ => None,
+        }
+    }
+
+    // Text of the cell in a column of the given type: its human-friendly form when it has one, otherwise its plain text
+    fn text(&self, column_type: &str) -> String {
+        self.formatted(column_type)
+            .unwrap_or_else(|| self.plain_text())
+    }
+
+    // Text of the cell in the table: numbers in a numeric column are written in the configured number format,
+    // anything else as its text
+    pub fn display(&self, column_type: &str, numbers: &NumberFormat) -> String {
+        match self {
+            Self::Int(number) if NUMERIC_TYPES.contains(&column_type) => numbers.integer(*number),
+            Self::Float(number) if NUMERIC_TYPES.contains(&column_type) => numbers.float(*number),
+            cell => cell.text(column_type),
+        }
+    }
+
+    // Plain text of the cell: strings without quotes, numbers and booleans as written and JSON in compact form
+    pub fn plain_text(&self) -> String {
+        match self {
+            Self::Int(number) => number.to_string(),
+            Self::Float(number) => number.to_string(),
+            Self::Str(text) => text.clone(),
+            Self::Bool(flag) => flag.to_string(),
+            Self::Null => String::from("null"),
+            Self::Json(value) => value.to_string(),
+        }
+    }
+}
+
+// How the latest poll into a channel went
+#[derive(Debug, Clone)]
+pub struct PollStatus {
+    pub at: Instant,           // When the poll finished
+    pub error: Option<String>, // Why it failed, None when it succeeded
+}
+
+// Documents posted to a channel other than the one on screen, swapped in when it is switched to
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Channel {
+    current_document: Log,  // Latest document received on the channel
+    history: VecDeque<Log>, // Previously received documents, oldest first
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            current_document: Log::new(),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Health {
+    status: &'static str,    // Always "ok" while the server is answering
+    documents_received: u64, // Number of documents received since startup
+    took: Option<TookStats>, // Query latency since startup or the last clear, null before any document
+}
+
+// What an instance reports about itself on "/info". Secrets are only reported as whether they are set.
+#[derive(Serialize, Debug)]
+struct InfoReply<'a> {
+    version: &'static str,   // Version of the dashboard, from the crate
+    address: &'a str,        // Where the server listens, as "ip:port" or a Unix socket's path
+    fields: &'a [String],    // Keys shown in the fields pane, from the config file
+    history_capacity: usize, // Maximum number of documents kept in history
+    features: Features,
+}
+
+#[derive(Serialize, Debug)]
+struct Features {
+    tls: bool,         // Whether the server speaks HTTPS
+    auth: bool,        // Whether writes require a bearer token
+    persistence: bool, // Whether received documents are appended to an output file
+}
+
+// Settings "/info" reports that only the server and main know, recorded in the state as they are decided
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    pub address: String,     // Where the server listens, filled in by server_thread
+    pub tls: bool,           // Whether the server speaks HTTPS, filled in by server_thread
+    pub fields: Vec<String>, // Keys shown in the fields pane, from the config file
+}
+
+// Query latency reported by Elasticsearch across received documents
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TookStats {
+    pub min: u32,     // Fastest took seen, in milliseconds
+    pub max: u32,     // Slowest took seen, in milliseconds
+    pub average: f64, // Exponential moving average of took, weighting recent documents more
+}
+
+impl TookStats {
+    fn new(took: u32) -> Self {
+        Self {
+            min: took,
+            max: took,
+            average: f64::from(took),
+        }
+    }
+
+    // Fold another document's took into the statistics
+    fn record(&mut self, took: u32) {
+        self.min = self.min.min(took);
+        self.max = self.max.max(took);
+        self.average += (f64::from(took) - self.average) * TOOK_AVERAGE_WEIGHT;
+    }
+}
+
+// Ingestion counters kept outside the application state's lock, so /health and /metrics can read them
+// without holding up ingestion or the draw thread. The took statistics sit behind their own small lock.
+#[derive(Debug, Default)]
+pub struct Counters {
+    documents: AtomicU64,    // Number of documents received since startup
+    rows: AtomicU64,         // Number of rows across every document received since startup
+    duplicates: AtomicU64,   // Number of documents --dedup left out of history since startup
+    current_took: AtomicU32, // took of the current document on screen, 0 when there is none
+    took_stats: Mutex<Option<TookStats>>, // Query latency across documents since startup or the last clear
+}
+
+impl Counters {
+    // Count a received document, following its took when it becomes the current document
+    fn record(&self, log: &Log, current: bool) {
+        self.documents.fetch_add(1, Ordering::Relaxed);
+        self.rows
+            .fetch_add(log.values.len() as u64, Ordering::Relaxed);
+        if current {
+            self.current_took.store(log.took, Ordering::Relaxed);
+        }
+        let mut stats = self.took_stats.lock().unwrap();
+        match &mut *stats {
+            Some(stats) => stats.record(log.took),
+            None => *stats = Some(TookStats::new(log.took)),
+        }
+    }
+
+    // Count a received document that repeated the latest one, which adds no rows
+    fn record_duplicate(&self) {
+        self.documents.fetch_add(1, Ordering::Relaxed);
+        self.duplicates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Start the took statistics over, leaving the totals counting since startup
+    fn clear(&self) {
+        self.current_took.store(0, Ordering::Relaxed);
+        *self.took_stats.lock().unwrap() = None;
+    }
+
+    pub fn documents(&self) -> u64 {
+        self.documents.load(Ordering::Relaxed)
+    }
+
+    fn rows(&self) -> u64 {
+        self.rows.load(Ordering::Relaxed)
+    }
+
+    fn duplicates(&self) -> u64 {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    fn current_took(&self) -> u32 {
+        self.current_took.load(Ordering::Relaxed)
+    }
+
+    pub fn took_stats(&self) -> Option<TookStats> {
+        *self.took_stats.lock().unwrap()
+    }
+}
+
+// Body of a POST to /data: one document, or an array of them ingested in order.
+// Deserialized by hand rather than untagged so a malformed document keeps serde's exact error and position.
+#[derive(Debug)]
+enum Posted {
+    One(Log),
+    Many(Vec<Log>),
+}
+
+impl<'de> Deserialize<'de> for Posted {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PostedVisitor;
+
+        impl<'de> de::Visitor<'de> for PostedVisitor {
+            type Value = Posted;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a document or an array of documents")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Posted, A::Error> {
+                Log::deserialize(de::value::MapAccessDeserializer::new(map)).map(Posted::One)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Posted, A::Error> {
+                Vec::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(Posted::Many)
+            }
+        }
+
+        deserializer.deserialize_any(PostedVisitor)
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+struct BulkSummary {
+    ingested: usize,  // Number of rows added to history, across all of the documents
+    documents: usize, // Number of documents those rows came in
+    errors: usize,    // Number of non-blank lines that failed to parse
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool, // Whether the body broke off partway, losing whatever was still to come
+}
+
+// Formats a document body can be posted in, and a reply sent back in. JSON needs no Content-Type,
+// and is what any type other than the binary ones is read as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFormat {
+    Json,        // application/json, the default
+    Cbor,        // application/cbor
+    MessagePack, // application/msgpack, or application/x-msgpack or application/vnd.msgpack
+}
+
+impl BodyFormat {
+    // The binary format a media type names, ignoring any parameters, None for every other type
+    fn binary(media_type: &str) -> Option<Self> {
+        let media_type = media_type.split(';').next().unwrap_or_default().trim();
+        match media_type.to_ascii_lowercase().as_str() {
+            "application/cbor" => Some(Self::Cbor),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            _ => None,
+        }
+    }
+
+    // The format of a body sent with the given Content-Type
+    fn of_content_type(content_type: Option<&str>) -> Self {
+        content_type.and_then(Self::binary).unwrap_or(Self::Json)
+    }
+
+    // Whether an Accept header lists this format's media type
+    fn accepted_by(self, accept: &str) -> bool {
+        accept
+            .split(',')
+            .filter_map(Self::binary)
+            .any(|format| format == self)
+    }
+
+    // A reply carrying the value in this format. A value that cannot be encoded gets a 500, as warp does for JSON
+    fn reply<T: Serialize>(self, value: &T) -> warp::reply::Response {
+        let (encoded, content_type) = match self {
+            Self::Json => return warp::reply::json(value).into_response(),
+            Self::Cbor => {
+                let mut encoded = vec![];
+                let result = ciborium::into_writer(value, &mut encoded).map(|_| encoded);
+                (result.map_err(|e| e.to_string()), "application/cbor")
+            }
+            Self::MessagePack => (
+                rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+                "application/msgpack",
+            ),
+        };
+        match encoded {
+            Ok(encoded) => {
+                warp::reply::with_header(encoded, "content-type", content_type).into_response()
+            }
+            Err(detail) => error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to encode reply",
+                detail,
+            ),
+        }
+    }
+}
+
+// Reasons a document body is refused, turned into error responses by handle_rejection
+#[derive(Debug)]
+enum BodyRejection {
+    UnsupportedEncoding(String), // Content-Encoding other than gzip or identity
+    CorruptGzip(String),         // Body claimed to be gzip but did not decompress
+    TooLarge,                    // Body was over the size limit as sent or once decompressed
+    Malformed(String), // Body did not deserialize into a Log, as whichever format it was sent in
+    MappedArray,       // An array posted with ?format=mapped, which only shapes a single document
+    Unreadable(String), // Body broke off before it had all arrived
+}
+
+// A document body that was refused, with the ID generated for the request that sent it
+#[derive(Debug)]
+struct RejectedBody {
+    request_id: String,    // ID echoed in the error response and logged with it
+    reason: BodyRejection, // Why the body was refused
+}
+
+impl warp::reject::Reject for RejectedBody {}
+
+// A write request without the configured bearer token
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+// A request turned away because the rate limit's bucket is empty
+#[derive(Debug)]
+struct RateLimited {
+    retry_after: Duration, // Time until the bucket holds a token again
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+// Token bucket limiting how many documents may be posted per second, shared by every connection.
+// It holds up to a second's worth of tokens, so short bursts get through at full speed.
+#[derive(Debug)]
+struct RateLimiter {
+    rate: f64,         // Tokens added per second, which is also the bucket's capacity
+    tokens: f64,       // Tokens currently in the bucket
+    refilled: Instant, // When tokens were last added
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: f64::from(rate),
+            tokens: f64::from(rate),
+            refilled: Instant::now(),
+        }
+    }
+
+    // Add the tokens earned since the last refill, up to the bucket's capacity
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled = now;
+    }
+
+    // Take a token if there is one, or say how long until there will be
+    fn try_acquire(&mut self) -> Result<(), Duration> {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    // Take tokens for documents already admitted, such as the rest of an array, running into
+    // debt if the bucket is short so later posts wait until it is paid off
+    fn charge(&mut self, tokens: usize) {
+        self.refill();
+        self.tokens -= tokens as f64;
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorReply {
+    error: &'static str, // Short description of what went wrong
+    detail: String,      // Underlying error message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>, // ID of the request, for errors about a document body
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AppState {
+    pub current_document: Log,                 // The current log document
+    live_document: Log, // Latest document on screen, trailing current_document by up to the debounce window
+    pub mapped_document: Vec<JsonMap>, // One map of column names to the live document's values per row
+    pub column_types: HashMap<String, String>, // A map of column names to their declared types
+    pub selected_row: Option<usize>, // Index into the displayed document's values of the highlighted row
+    pub history: VecDeque<Log>,      // Previously received documents, oldest first
+    pub channel: String, // Channel on screen, whose documents are current_document and history
+    pub channels: BTreeMap<String, Channel>, // Every other channel documents have been posted to, by name
+    history_capacity: usize,                 // Maximum number of documents kept in history
+    pub history_cursor: Option<usize>,       // History entry being viewed, None to follow live data
+    pub last_error: Option<String>,          // Most recent non-fatal error, shown in the UI
+    pub server_error: Option<String>, // Why the server stopped listening, kept on screen for good
+    #[serde(skip)]
+    pub counters: Arc<Counters>, // Ingestion counters, shared with the endpoints that report them
+    #[serde(skip)]
+    pub redraw: Option<SyncSender<()>>, // Wakes the draw thread when something on screen changes
+    #[serde(skip)]
+    pub received: Option<SyncSender<Log>>, // Hands --once the first document that arrives
+    #[serde(skip)]
+    #[cfg(feature = "tui")]
+    pub input_mode: InputMode, // Whether key presses navigate or edit the filter
+    pub filter: String,               // Case-insensitive text a row must contain to be shown
+    pub highlight_query: String,      // Text typed into the highlight prompt
+    #[serde(skip)]
+    pub highlight: Option<Regex>, // Pattern whose matching cells are emphasised, None when nothing is highlighted
+    #[serde(skip)]
+    pub highlight_error: Option<String>, // Why the typed pattern was refused, shown in the prompt
+    pub selected_col: usize, // Index into the displayed document's columns of the highlighted column
+    pub hidden_columns: HashSet<String>, // Columns left out of the table, fields pane and inspector
+    pub column_picker: usize, // Index into the displayed document's columns of the column picker's cursor
+    pub palette_query: String, // Text the command palette's entries are filtered by
+    pub palette_selected: usize, // Index into the filtered command palette entries of the highlighted one
+    #[serde(skip)]
+    #[cfg(feature = "tui")]
+    pub action_keys: HashMap<Action, Vec<String>>, // Names of the keys each action is bound to, for the command palette
+    pub sort: Option<(usize, SortDirection)>, // Column the rows are ordered by, None for insertion order
+    pub inspector: bool, // Whether the selected row's fields are shown below the table
+    pub type_warnings: Vec<String>, // Values in the current document that do not match their column's declared type
+    pub warnings_panel: bool,       // Whether the type warnings are listed below the table
+    pub inspector_scroll: u16,      // Number of lines the inspector pane is scrolled down
+    pub wrap: bool, // Whether long values in the inspector wrap onto further lines instead of being cut off
+    pub help: bool, // Whether the footer hinting at the keys of the current mode is shown
+    pub sizes: bool, // Whether the inspector shows each value's size as JSON, sorted largest first
+    pub split: bool, // Whether the two most recent documents are summarised side by side
+    pub expanded: bool, // Whether the selected column is shown at full width until the selection moves
+    pub paused: bool, // Whether the display is frozen on the snapshot while documents keep arriving
+    snapshot: Option<Log>, // Document shown while paused, taken at the moment of pausing
+    pub search: Search, // State of the history search
+    pub histogram: bool, // Whether the histogram of the selected column is shown below the table
+    pub histogram_buckets: usize, // Number of buckets the histogram splits the column's range into
+    pub feed: bool, // Whether history is shown as one line per document instead of the fields and table
+    pub follow: bool, // Whether the feed sticks to the newest document as new ones arrive
+    pub feed_scroll: usize, // Number of documents the feed is scrolled up from the newest one
+    pub diff: bool, // Whether fields that differ from the previous document are highlighted
+    #[serde(skip)]
+    pub changes: HashMap<String, Change>, // Fields of the selected row that differ from the previous document, in diff mode
+    pub history_order: HistoryOrder, // Order in which [ and ] step through history
+    pub time_range: TimeRange, // Window of @timestamp the feed, search and [ and ] are limited to
+    #[serde(skip)]
+    pub status_message: Option<(String, Instant)>, // Transient notice for the status bar and when it was set
+    #[serde(skip)]
+    pub aliases: HashMap<String, String>, // Display labels for field names, from the config file
+    #[serde(skip)]
+    #[cfg(feature = "tui")]
+    pub theme: Theme, // Colours of the dashboard, from the config file or the saved state
+    #[serde(skip)]
+    pub numbers: NumberFormat, // How numbers in numeric columns of the table are written, from the config file
+    #[serde(skip)]
+    pub symbols: HashMap<String, Symbol>, // Symbols boolean cells are drawn as, by field name or column type, from the config file
+    pub ascii: bool, // Whether to draw only ASCII, spelling symbols with ASCII characters
+    pub redact: HashSet<String>, // Fields whose values are masked on screen and in POST responses, from the config file
+    pub redact_mode: RedactMode, // Whether redacted values are dropped as documents arrive
+    revealed_row: Option<usize>, // Row whose redacted values are shown while it stays selected
+    pub has_data: bool,          // Whether a document has arrived since startup or the last clear
+    pub dedup: bool, // Whether a document repeating the latest one on its channel is left out of history
+    pub dedup_key: Option<String>, // Column --dedup compares, every value when unset
+    pub history_ttl: Option<Duration>, // How long a document stays in history, None to keep it until the count cap evicts it
+    fingerprints: HashMap<String, u64>, // Hash of the latest document on each channel, kept for --dedup
+    pub source: String, // Where documents are expected from, shown until the first one arrives
+    #[serde(skip)]
+    pub export_dir: PathBuf, // Directory CSV exports are written to
+    #[serde(skip)]
+    pub debounce: Duration, // Shortest time between changes of the live document
+    #[serde(skip, default = "Instant::now")]
+    last_shown: Instant, // When the live document last changed
+    display_pending: bool, // Whether current_document is newer than the live document on screen
+    #[serde(skip)]
+    pub last_update: Option<Instant>, // When the latest document arrived on any channel, None before the first
+    #[serde(skip)]
+    pub polls: HashMap<String, PollStatus>, // Outcome of the latest poll of each polled channel
+    #[serde(skip)]
+    pub stale_after: Duration, // Time without a document after which the data counts as stale
+    #[serde(skip)]
+    pub alert_rules: Vec<AlertRule>, // Rules from the config file checked against every received document
+    #[serde(skip)]
+    pub alert: Option<Alert>, // Raised alert waiting to be acknowledged, None when there is none
+    #[serde(skip)]
+    pub alerts_raised: u64, // Alerts raised so far, which the draw thread rings the bell for as the count goes up
+    #[serde(skip)]
+    pub output: Option<BufWriter<File>>, // NDJSON file received documents are appended to
+    #[serde(skip)]
+    pub info: Info, // Settings reported by "/info"
+    #[serde(skip, default = "stream_channel")]
+    updates: broadcast::Sender<Arc<str>>, // Every received document as JSON, for WebSocket clients
+}
+
+// How a field of the document on screen differs from the previous document, for diff mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Changed, // Present in both documents with different values
+    Added,   // Present only in the document on screen
+    Removed, // Present only in the previous document
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct PostOptions {
+    format: ResponseFormat, // Shape of the document the POST replies with
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ResponseFormat {
+    #[default]
+    Raw, // The Log as it was stored
+    Mapped, // One object per row, keyed by column name
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct DataQuery {
+    fields: Option<String>, // Comma-separated fields to return from each row, the whole document when unset
+    nulls: bool,            // Return fields a row lacks as null instead of leaving them out
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct HistoryQuery {
+    limit: Option<usize>, // Most documents to return, all of them when unset
+    offset: usize,        // Number of the most recent documents to skip
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct StreamOptions {
+    current: bool, // Send the current document as soon as the client connects
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    #[default]
+    Normal, // Keys navigate the dashboard
+    Filter,    // Keys edit the filter query
+    Search,    // Keys edit the history search query
+    Results,   // Keys browse the history search results
+    Columns,   // Keys move through the column picker and toggle columns
+    Highlight, // Keys edit the highlight pattern
+    Palette,   // Keys filter and pick from the command palette
+}
+
+// Everything a key can do on the dashboard, named in the config file's keybindings in snake_case
+#[cfg(feature = "tui")]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,         // Exit the application
+    Filter,       // Start editing the row filter
+    Search,       // Start editing the history search
+    Down,         // Select the next row
+    Up,           // Select the previous row
+    Top,          // Select the first row
+    Bottom,       // Select the last row
+    Left,         // Scroll the columns left
+    Right,        // Scroll the columns right
+    ColumnLeft,   // Move the column selection left
+    ColumnRight,  // Move the column selection right
+    Sort,         // Cycle sorting on the selected column
+    Clear,        // Drop every received document
+    Copy,         // Copy the selected row
+    Split,        // Toggle the side by side summary of the two latest documents
+    Expand,       // Toggle showing the selected column at full width
+    Pause,        // Freeze or resume the display
+    Histogram,    // Toggle the histogram of the selected column
+    MoreBuckets,  // Add a histogram bucket
+    FewerBuckets, // Remove a histogram bucket
+    Older,        // Step back through history
+    Newer,        // Step forward through history
+    HistoryOrder, // Cycle the order history is stepped through in
+    TimeRange,    // Cycle the window of @timestamp history is limited to
+    Acknowledge,  // Clear a raised alert
+    NextChannel,  // Switch to the next channel
+    Feed,         // Toggle the feed view
+    Warnings,     // Toggle the list of type warnings
+    Export,       // Write the document on screen to a CSV file
+    Diff,         // Toggle highlighting what changed since the previous document
+    #[serde(rename = "recent_1")]
+    Recent1, // Show the latest document
+    #[serde(rename = "recent_2")]
+    Recent2, // Show the document before the latest
+    #[serde(rename = "recent_3")]
+    Recent3, // Show the third most recent document
+    #[serde(rename = "recent_4")]
+    Recent4, // Show the fourth most recent document
+    #[serde(rename = "recent_5")]
+    Recent5, // Show the fifth most recent document
+    Inspector,    // Toggle the inspector
+    CloseInspector, // Close the inspector
+    InspectorDown, // Scroll the inspector down
+    InspectorUp,  // Scroll the inspector up
+    InspectorPageDown, // Scroll the inspector down a page
+    InspectorPageUp, // Scroll the inspector up a page
+    Wrap,         // Toggle wrapping long values in the inspector
+    Sizes,        // Toggle showing the size of each value in the inspector, largest first
+    Columns,      // Open the column picker
+    Highlight,    // Start editing the highlight pattern
+    Palette,      // Open the command palette
+    Reveal,       // Toggle showing the redacted values of the selected row
+    Help,         // Toggle the key hints footer
+}
+
+#[cfg(feature = "tui")]
+impl Action {
+    // Name of the action as the config file's keybindings spell it
+    pub fn name(self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|name| name.as_str().map(String::from))
+            .unwrap_or_default()
+    }
+}
+
+// What the key bindings and the command palette know about an action
+#[cfg(feature = "tui")]
+pub struct ActionInfo {
+    pub action: Action,
+    pub description: &'static str, // What the action does, as listed in the command palette
+    keys: &'static [KeyCode], // Keys the action is bound to unless the config file says otherwise
+}
+
+// Every action, in the order the command palette lists them
+#[cfg(feature = "tui")]
+const ACTIONS: [ActionInfo; 48] = [
+    ActionInfo {
+        action: Action::Quit,
+        description: "Exit the dashboard",
+        keys: &[KeyCode::Char('q')],
+    },
+    ActionInfo {
+        action: Action::Filter,
+        description: "Filter the rows by text",
+        keys: &[KeyCode::Char('/')],
+    },
+    ActionInfo {
+        action: Action::Search,
+        description: "Search every document in history",
+        keys: &[KeyCode::Char('f')],
+    },
+    ActionInfo {
+        action: Action::Down,
+        description: "Select the next row",
+        keys: &[KeyCode::Down, KeyCode::Char('j')],
+    },
+    ActionInfo {
+        action: Action::Up,
+        description: "Select the previous row",
+        keys: &[KeyCode::Up, KeyCode::Char('k')],
+    },
+    ActionInfo {
+        action: Action::Top,
+        description: "Jump to the first row",
+        keys: &[KeyCode::Home, KeyCode::Char('g')],
+    },
+    ActionInfo {
+        action: Action::Bottom,
+        description: "Jump to the last row",
+        keys: &[KeyCode::End, KeyCode::Char('G')],
+    },
+    ActionInfo {
+        action: Action::Left,
+        description: "Scroll the columns left",
+        keys: &[KeyCode::Left, KeyCode::Char('h')],
+    },
+    ActionInfo {
+        action: Action::Right,
+        description: "Scroll the columns right",
+        keys: &[KeyCode::Right, KeyCode::Char('l')],
+    },
+    ActionInfo {
+        action: Action::ColumnLeft,
+        description: "Move the column selection left",
+        keys: &[KeyCode::Char('<')],
+    },
+    ActionInfo {
+        action: Action::ColumnRight,
+        description: "Move the column selection right",
+        keys: &[KeyCode::Char('>')],
+    },
+    ActionInfo {
+        action: Action::Sort,
+        description: "Sort by the selected column",
+        keys: &[KeyCode::Char('s')],
+    },
+    ActionInfo {
+        action: Action::Clear,
+        description: "Clear the current document and history",
+        keys: &[KeyCode::Char('c')],
+    },
+    ActionInfo {
+        action: Action::Copy,
+        description: "Copy the selected row as JSON",
+        keys: &[KeyCode::Char('y')],
+    },
+    ActionInfo {
+        action: Action::Split,
+        description: "Compare the two latest documents side by side",
+        keys: &[KeyCode::Char('|')],
+    },
+    ActionInfo {
+        action: Action::Expand,
+        description: "Show the selected column at full width",
+        keys: &[KeyCode::Char('z')],
+    },
+    ActionInfo {
+        action: Action::Pause,
+        description: "Pause or resume the display",
+        keys: &[KeyCode::Char(' ')],
+    },
+    ActionInfo {
+        action: Action::Histogram,
+        description: "Show a histogram of the selected column",
+        keys: &[KeyCode::Char('b')],
+    },
+    ActionInfo {
+        action: Action::MoreBuckets,
+        description: "Add a histogram bucket",
+        keys: &[KeyCode::Char('+')],
+    },
+    ActionInfo {
+        action: Action::FewerBuckets,
+        description: "Remove a histogram bucket",
+        keys: &[KeyCode::Char('-')],
+    },
+    ActionInfo {
+        action: Action::Older,
+        description: "Step back through history",
+        keys: &[KeyCode::Char('[')],
+    },
+    ActionInfo {
+        action: Action::Newer,
+        description: "Step forward through history",
+        keys: &[KeyCode::Char(']')],
+    },
+    ActionInfo {
+        action: Action::HistoryOrder,
+        description: "Change the order history is stepped through in",
+        keys: &[KeyCode::Char('o')],
+    },
+    ActionInfo {
+        action: Action::TimeRange,
+        description: "Limit history to the last 1m, 5m, 15m or 1h by @timestamp",
+        keys: &[KeyCode::Char('T')],
+    },
+    ActionInfo {
+        action: Action::Acknowledge,
+        description: "Acknowledge a raised alert",
+        keys: &[KeyCode::Char('a')],
+    },
+    ActionInfo {
+        action: Action::NextChannel,
+        description: "Switch to the next channel",
+        keys: &[KeyCode::Tab],
+    },
+    ActionInfo {
+        action: Action::Feed,
+        description: "Toggle the feed view",
+        keys: &[KeyCode::Char('v')],
+    },
+    ActionInfo {
+        action: Action::Warnings,
+        description: "List values that do not match their column type",
+        keys: &[KeyCode::Char('W')],
+    },
+    ActionInfo {
+        action: Action::Export,
+        description: "Export the document on screen to CSV",
+        keys: &[KeyCode::Char('e')],
+    },
+    ActionInfo {
+        action: Action::Diff,
+        description: "Highlight what changed since the previous document",
+        keys: &[KeyCode::Char('d')],
+    },
+    ActionInfo {
+        action: Action::Recent1,
+        description: "Show the latest document",
+        keys: &[KeyCode::Char('1')],
+    },
+    ActionInfo {
+        action: Action::Recent2,
+        description: "Show the second most recent document",
+        keys: &[KeyCode::Char('2')],
+    },
+    ActionInfo {
+        action: Action::Recent3,
+        description: "Show the third most recent document",
+        keys: &[KeyCode::Char('3')],
+    },
+    ActionInfo {
+        action: Action::Recent4,
+        description: "Show the fourth most recent document",
+        keys: &[KeyCode::Char('4')],
+    },
+    ActionInfo {
+        action: Action::Recent5,
+        description: "Show the fifth most recent document",
+        keys: &[KeyCode::Char('5')],
+    },
+    ActionInfo {
+        action: Action::Inspector,
+        description: "Open or close the inspector",
+        keys: &[KeyCode::Enter],
+    },
+    ActionInfo {
+        action: Action::CloseInspector,
+        description: "Close the inspector",
+        keys: &[KeyCode::Esc],
+    },
+    ActionInfo {
+        action: Action::InspectorDown,
+        description: "Scroll the inspector down a line",
+        keys: &[KeyCode::Char('J')],
+    },
+    ActionInfo {
+        action: Action::InspectorUp,
+        description: "Scroll the inspector up a line",
+        keys: &[KeyCode::Char('K')],
+    },
+    ActionInfo {
+        action: Action::InspectorPageDown,
+        description: "Scroll the inspector down a page",
+        keys: &[KeyCode::PageDown],
+    },
+    ActionInfo {
+        action: Action::InspectorPageUp,
+        description: "Scroll the inspector up a page",
+        keys: &[KeyCode::PageUp],
+    },
+    ActionInfo {
+        action: Action::Wrap,
+        description: "Wrap long values in the inspector",
+        keys: &[KeyCode::Char('w')],
+    },
+    ActionInfo {
+        action: Action::Sizes,
+        description: "Show value sizes in the inspector, largest first",
+        keys: &[KeyCode::Char('L')],
+    },
+    ActionInfo {
+        action: Action::Columns,
+        description: "Pick the columns to show",
+        keys: &[KeyCode::Char('C')],
+    },
+    ActionInfo {
+        action: Action::Highlight,
+        description: "Highlight cells matching a regex",
+        keys: &[KeyCode::Char('r')],
+    },
+    ActionInfo {
+        action: Action::Palette,
+        description: "Open the command palette",
+        keys: &[KeyCode::Char(':')],
+    },
+    ActionInfo {
+        action: Action::Reveal,
+        description: "Reveal the redacted values of the selected row",
+        keys: &[KeyCode::Char('u')],
+    },
+    ActionInfo {
+        action: Action::Help,
+        description: "Show or hide the key hints footer",
+        keys: &[KeyCode::Char('?')],
+    },
+];
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryOrder {
+    #[default]
+    Received, // Step through history in the order documents arrived
+    NewestFirst, // Step through history by @timestamp, newest first
+    OldestFirst, // Step through history by @timestamp, oldest first
+}
+
+impl HistoryOrder {
+    pub fn label(self) -> &'static str {
+        match self {
+            HistoryOrder::Received => "as received",
+            HistoryOrder::NewestFirst => "newest @timestamp first",
+            HistoryOrder::OldestFirst => "oldest @timestamp first",
+        }
+    }
+}
+
+// Window of @timestamp that history is limited to, counting back from now
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimeRange {
+    #[default]
+    All, // Every entry, including those without a valid @timestamp
+    LastMinute,         // Entries from the last minute
+    LastFiveMinutes,    // Entries from the last 5 minutes
+    LastFifteenMinutes, // Entries from the last 15 minutes
+    LastHour,           // Entries from the last hour
+}
+
+impl TimeRange {
+    const PRESETS: [TimeRange; 5] = [
+        TimeRange::LastMinute,
+        TimeRange::LastFiveMinutes,
+        TimeRange::LastFifteenMinutes,
+        TimeRange::LastHour,
+        TimeRange::All,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TimeRange::All => "all",
+            TimeRange::LastMinute => "1m",
+            TimeRange::LastFiveMinutes => "5m",
+            TimeRange::LastFifteenMinutes => "15m",
+            TimeRange::LastHour => "1h",
+        }
+    }
+
+    // How far back the range reaches, None for every entry
+    pub fn window(self) -> Option<chrono::Duration> {
+        match self {
+            TimeRange::All => None,
+            TimeRange::LastMinute => Some(chrono::Duration::minutes(1)),
+            TimeRange::LastFiveMinutes => Some(chrono::Duration::minutes(5)),
+            TimeRange::LastFifteenMinutes => Some(chrono::Duration::minutes(15)),
+            TimeRange::LastHour => Some(chrono::Duration::hours(1)),
+        }
+    }
+
+    // Every preset in the order T cycles through them, the active one in brackets, as in "1m [5m] 15m 1h all"
+    pub fn presets(self) -> String {
+        Self::PRESETS
+            .iter()
+            .map(|&range| {
+                if range == self {
+                    format!("[{}]", range.label())
+                } else {
+                    range.label().to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Search {
+    pub query: String, // Text typed into the search prompt, optionally prefixed with "field:"
+    pub exact: bool,   // Whether values must equal the query rather than contain it
+    pub field: String, // Field the last search looked at
+    pub hits: Vec<SearchHit>, // Rows matched by the last search, most recent document first
+    pub selected: usize, // Index into hits of the highlighted result
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchHit {
+    pub history_index: usize, // History entry holding the row, 0 being the most recent
+    pub row: usize,           // Index of the row in the entry's values
+    pub value: String,        // Text of the matching value
+}
+
+impl AppState {
+    pub fn new(history_capacity: usize) -> SharedAppState {
+        Arc::new(RwLock::new(Self {
+            current_document: Log::new(),
+            live_document: Log::new(),
+            mapped_document: vec![],
+            column_types: HashMap::new(),
+            selected_row: None,
+            history: VecDeque::with_capacity(history_capacity),
+            channel: DEFAULT_CHANNEL.to_string(),
+            channels: BTreeMap::new(),
+            history_capacity,
+            history_cursor: None,
+            last_error: None,
+            server_error: None,
+            counters: Arc::default(),
+            redraw: None,
+            received: None,
+            #[cfg(feature = "tui")]
+            input_mode: InputMode::Normal,
+            filter: String::new(),
+            highlight_query: String::new(),
+            highlight: None,
+            highlight_error: None,
+            selected_col: 0,
+            hidden_columns: HashSet::new(),
+            column_picker: 0,
+            palette_query: String::new(),
+            palette_selected: 0,
+            #[cfg(feature = "tui")]
+            action_keys: HashMap::new(),
+            sort: None,
+            inspector: false,
+            type_warnings: vec![],
+            warnings_panel: false,
+            split: false,
+            expanded: false,
+            paused: false,
+            snapshot: None,
+            search: Search::default(),
+            histogram: false,
+            histogram_buckets: HISTOGRAM_BUCKETS,
+            feed: false,
+            follow: true,
+            feed_scroll: 0,
+            diff: false,
+            changes: HashMap::new(),
+            history_order: HistoryOrder::Received,
+            time_range: TimeRange::All,
+            inspector_scroll: 0,
+            wrap: false,
+            help: true,
+            sizes: false,
+            status_message: None,
+            aliases: HashMap::new(),
+            #[cfg(feature = "tui")]
+            theme: Theme::default(),
+            numbers: NumberFormat::default(),
+            symbols: HashMap::new(),
+            ascii: false,
+            redact: HashSet::new(),
+            redact_mode: RedactMode::default(),
+            revealed_row: None,
+            has_data: false,
+            dedup: false,
+            dedup_key: None,
+            history_ttl: None,
+            fingerprints: HashMap::new(),
+            source: String::new(),
+            export_dir: PathBuf::new(),
+            debounce: Duration::ZERO,
+            last_shown: Instant::now(),
+            display_pending: false,
+            last_update: None,
+            polls: HashMap::new(),
+            stale_after: Duration::from_secs(STALE_SECS),
+            alert_rules: vec![],
+            alert: None,
+            alerts_raised: 0,
+            output: None,
+            info: Info::default(),
+            updates: stream_channel(),
+        }))
+    }
+
+    // Ask the draw thread to redraw without waiting for the refresh interval.
+    // The channel holds a single pending request, so bursts collapse into one redraw.
+    pub fn request_redraw(&self) {
+        if let Some(redraw) = &self.redraw {
+            let _ = redraw.try_send(());
+        }
+    }
+
+    // Record a document in history, evicting the oldest one when full
+    fn push_log(&mut self, log: Log) {
+        push_bounded(&mut self.history, self.history_capacity, log);
+    }
+
+    // Map the live document for display and check its values against their declared types.
+    // A document without rows has nothing to map
+    fn remap(&mut self) {
+        self.mapped_document = if self.live_document.values.is_empty() {
+            vec![]
+        } else {
+            map_document(&self.live_document)
+        };
+        self.column_types = map_column_types(&self.live_document);
+        self.type_warnings = type_warnings(&self.live_document, &self.redact, self.ascii);
+        if !self.type_warnings.is_empty() {
+            tracing::warn!(
+                count = self.type_warnings.len(),
+                "values do not match their column types"
+            );
+        }
+    }
+
+    // Put the current document on screen and map it
+    fn show_latest(&mut self) {
+        self.live_document = self.current_document.clone();
+        self.revealed_row = None;
+        self.remap();
+        self.last_shown = Instant::now();
+        self.display_pending = false;
+    }
+
+    // Catch the screen up with a document held back by the debounce window once the window has passed
+    pub fn show_pending(&mut self) {
+        if self.display_pending && self.last_shown.elapsed() >= self.debounce {
+            self.show_latest();
+            self.clamp_selection();
+        }
+    }
+
+    // How long until a held back document may be shown, None when nothing is waiting
+    pub fn pending_wait(&self) -> Option<Duration> {
+        self.display_pending
+            .then(|| (self.last_shown + self.debounce).saturating_duration_since(Instant::now()))
+    }
+
+    // The latest document of a channel, None for a channel nothing has been posted to
+    fn channel_document(&self, name: &str) -> Option<&Log> {
+        if name == self.channel {
+            return Some(&self.current_document);
+        }
+        self.channels
+            .get(name)
+            .map(|channel| &channel.current_document)
+    }
+
+    // The history of a channel, oldest first, None for a channel nothing has been posted to
+    fn channel_history(&self, name: &str) -> Option<&VecDeque<Log>> {
+        if name == self.channel {
+            return Some(&self.history);
+        }
+        self.channels.get(name).map(|channel| &channel.history)
+    }
+
+    // Switch the screen to the next channel in name order, wrapping around after the last one.
+    // The outgoing channel's documents are put away, the incoming channel's take their place
+    // and the view goes back to following its latest document.
+    pub fn next_channel(&mut self) {
+        let next = self
+            .channels
+            .range::<str, _>((Bound::Excluded(self.channel.as_str()), Bound::Unbounded))
+            .next()
+            .or_else(|| self.channels.iter().next())
+            .map(|(name, _)| name.clone());
+        let Some(next) = next else {
+            self.notify(format!("only channel {}", self.channel));
+            return;
+        };
+
+        let incoming = self.channels.remove(&next).unwrap_or_else(Channel::new);
+        let outgoing = Channel {
+            current_document: std::mem::replace(
+                &mut self.current_document,
+                incoming.current_document,
+            ),
+            history: std::mem::replace(&mut self.history, incoming.history),
+        };
+        let previous = std::mem::replace(&mut self.channel, next);
+        self.channels.insert(previous, outgoing);
+        self.counters
+            .current_took
+            .store(self.current_document.took, Ordering::Relaxed);
+
+        self.show_latest();
+        self.history_cursor = None;
+        self.paused = false;
+        self.snapshot = None;
+        self.search.hits.clear();
+        self.selected_row = None;
+        self.inspector_scroll = 0;
+        self.follow_feed();
+        self.clamp_selection();
+        self.notify(format!("channel {}", self.channel));
+    }
+
+    // Scroll the feed by a number of documents, positive towards older ones, within the ends of history.
+    // Scrolling up stops it following the newest document, and scrolling back down to it follows again.
+    pub fn scroll_feed(&mut self, delta: isize) {
+        self.feed_scroll = self
+            .feed_scroll
+            .saturating_add_signed(delta)
+            .min(self.history_len().saturating_sub(1));
+        if self.feed_scroll == 0 {
+            self.follow_feed();
+        } else if delta > 0 {
+            self.follow = false;
+        }
+    }
+
+    // Go back to the newest document in the feed and stick to it as new ones arrive
+    pub fn follow_feed(&mut self) {
+        self.follow = true;
+        self.feed_scroll = 0;
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    // Fetch a document from history, where index 0 is the most recent one
+    pub fn get_historical(&self, index: usize) -> Option<&Log> {
+        let len = self.history.len();
+        index
+            .checked_add(1)
+            .and_then(|offset| len.checked_sub(offset))
+            .and_then(|position| self.history.get(position))
+    }
+
+    // The document currently on screen, either the live one or a history entry
+    pub fn displayed_document(&self) -> &Log {
+        self.history_cursor
+            .and_then(|index| self.get_historical(index))
+            .or(self.snapshot.as_ref())
+            .unwrap_or(&self.live_document)
+    }
+
+    // Whether the live document is on screen, as opposed to a history entry or the paused snapshot
+    pub fn is_live(&self) -> bool {
+        self.history_cursor.is_none() && !self.paused
+    }
+
+    // Freeze the display on the document currently shown, or resume following the latest one
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if self.paused {
+            self.snapshot = Some(self.displayed_document().clone());
+            self.history_cursor = None;
+        } else {
+            self.snapshot = None;
+            self.history_cursor = None;
+            self.clamp_selection();
+        }
+    }
+
+    // History indices in the order [ and ] step through them: as received, or by @timestamp with
+    // entries lacking a valid timestamp placed after the rest in the order they were received.
+    // Entries outside the time range are left out.
+    pub fn history_sequence(&self) -> Vec<usize> {
+        let mut sequence: Vec<usize> = (0..self.history_len())
+            .filter(|&index| {
+                self.get_historical(index)
+                    .is_some_and(|log| self.in_time_range(log))
+            })
+            .collect();
+        if self.history_order == HistoryOrder::Received {
+            return sequence;
+        }
+
+        let timestamps: Vec<Option<DateTime<Utc>>> = (0..self.history_len())
+            .map(|index| self.get_historical(index).and_then(log_timestamp))
+            .collect();
+        let newest_first = self.history_order == HistoryOrder::NewestFirst;
+        sequence.sort_by(|&a, &b| match (timestamps[a], timestamps[b]) {
+            (Some(a), Some(b)) if newest_first => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => cmp::Ordering::Less,
+            (None, Some(_)) => cmp::Ordering::Greater,
+            (None, None) => cmp::Ordering::Equal,
+        });
+        sequence
+    }
+
+    // Position of the viewed history entry within a sequence, live data counting as the latest document
+    pub fn history_position(&self, sequence: &[usize]) -> usize {
+        let index = self.history_cursor.unwrap_or(0);
+        sequence
+            .iter()
+            .position(|&entry| entry == index)
+            .unwrap_or_default()
+    }
+
+    // Step one document further along the history order, stopping at the last.
+    // From live data this starts at the first entry, skipping it when it is the live document itself.
+    pub fn history_older(&mut self) {
+        let sequence = self.history_sequence();
+        if sequence.is_empty() {
+            return;
+        }
+        let next = match self.history_cursor {
+            None if sequence[0] != 0 => 0,
+            _ => self.history_position(&sequence) + 1,
+        };
+        let next = next.min(sequence.len() - 1);
+        self.history_cursor = Some(sequence[next]);
+        self.clamp_selection();
+    }
+
+    // Step one document back along the history order, returning to live data past the first
+    pub fn history_newer(&mut self) {
+        if self.history_cursor.is_some() {
+            let sequence = self.history_sequence();
+            self.history_cursor = match self.history_position(&sequence) {
+                0 => None,
+                position => Some(sequence[position - 1]),
+            };
+        }
+        self.clamp_selection();
+    }
+
+    // Show one of the recent documents in the tab bar, where 0 is the latest and returns to live data.
+    // Tabs past the end of history are ignored.
+    pub fn show_recent(&mut self, tab: usize) {
+        if tab >= self.history_len().min(RECENT_TABS) {
+            return;
+        }
+        self.history_cursor = (tab > 0).then_some(tab);
+        self.clamp_selection();
+    }
+
+    // The tab of the document on screen, None when it is older than the tab bar reaches
+    pub fn recent_tab(&self) -> Option<usize> {
+        match self.history_cursor {
+            None => Some(0),
+            Some(index) => (index < RECENT_TABS).then_some(index),
+        }
+    }
+
+    // Work out which fields of the selected row differ from the same row of the document received before
+    // the one on screen, looking at every column of either document and the configured fields.
+    // Nothing is marked outside diff mode, at the oldest history entry or while paused,
+    // as the snapshot's place in history moves on as documents arrive.
+    pub fn update_changes(&mut self, fields: &[String]) {
+        self.changes.clear();
+        if !self.diff || self.paused {
+            return;
+        }
+        let Some(previous) = self.get_historical(self.history_cursor.unwrap_or(0) + 1) else {
+            return;
+        };
+        let row = self.selected_row.unwrap_or(0);
+        let previous = map_row(previous, row);
+        let current = map_row(self.displayed_document(), row);
+        let keys: HashSet<&String> = current
+            .keys()
+            .chain(previous.keys())
+            .chain(fields)
+            .collect();
+        self.changes = keys
+            .into_iter()
+            .filter_map(|key| {
+                let change = match (field_value(&current, key), field_value(&previous, key)) {
+                    (Some(now), Some(before)) if now != before => Change::Changed,
+                    (Some(_), None) => Change::Added,
+                    (None, Some(_)) => Change::Removed,
+                    _ => return None,
+                };
+                Some((key.clone(), change))
+            })
+            .collect();
+    }
+
+    // Cycle the history order: as received, then by @timestamp newest first, then oldest first
+    pub fn cycle_history_order(&mut self) {
+        self.history_order = match self.history_order {
+            HistoryOrder::Received => HistoryOrder::NewestFirst,
+            HistoryOrder::NewestFirst => HistoryOrder::OldestFirst,
+            HistoryOrder::OldestFirst => HistoryOrder::Received,
+        };
+        self.notify(format!("history order: {}", self.history_order.label()));
+    }
+
+    // Whether a document falls in the time range. Without a valid @timestamp it is only in "all"
+    pub fn in_time_range(&self, log: &Log) -> bool {
+        match self.time_range.window() {
+            None => true,
+            Some(window) => log_timestamp(log).is_some_and(|at| Utc::now() - at <= window),
+        }
+    }
+
+    // Cycle the time range through its presets. The history entry on screen and search hits
+    // that fall outside the new range are let go, returning the view to live data.
+    pub fn cycle_time_range(&mut self) {
+        let presets = TimeRange::PRESETS;
+        let position = presets.iter().position(|&range| range == self.time_range);
+        self.time_range = presets[position.map_or(0, |position| (position + 1) % presets.len())];
+
+        if let Some(index) = self.history_cursor {
+            if !self
+                .get_historical(index)
+                .is_some_and(|log| self.in_time_range(log))
+            {
+                self.history_cursor = None;
+            }
+        }
+        let hits = std::mem::take(&mut self.search.hits);
+        self.search.hits = hits
+            .into_iter()
+            .filter(|hit| {
+                self.get_historical(hit.history_index)
+                    .is_some_and(|log| self.in_time_range(log))
+            })
+            .collect();
+        self.search.selected = self
+            .search
+            .selected
+            .min(self.search.hits.len().saturating_sub(1));
+        self.clamp_selection();
+        self.notify(format!("time range: {}", self.time_range.presets()));
+    }
+
+    // Indices of the displayed document's rows that pass the filter, in display order.
+    // Sorting only reorders these indices, so the document itself keeps its insertion order.
+    pub fn visible_rows(&self) -> Vec<usize> {
+        let document = self.displayed_document();
+        let query = self.filter.to_lowercase();
+        let mut rows: Vec<usize> = document
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| query.is_empty() || row_matches(row, &query))
+            .map(|(i, _)| i)
+            .collect();
+
+        if let Some((column, direction)) = self.sort {
+            rows.sort_by(|&a, &b| {
+                let ordering = compare_cells(document.cell(a, column), document.cell(b, column));
+                match direction {
+                    SortDirection::Ascending => ordering,
+                    SortDirection::Descending => ordering.reverse(),
+                }
+            });
+        }
+
+        rows
+    }
+
+    // Move the column selection left or right over the shown columns, stopping at the first and last of them
+    pub fn move_column_selection(&mut self, delta: isize) {
+        let columns = self.displayed_document().columns.len();
+        self.expanded = false;
+        for _ in 0..delta.unsigned_abs() {
+            let next = if delta > 0 {
+                (self.selected_col + 1..columns).find(|&i| !self.is_hidden(i))
+            } else {
+                (0..self.selected_col).rev().find(|&i| !self.is_hidden(i))
+            };
+            match next {
+                Some(next) => self.selected_col = next,
+                None => break,
+            }
+        }
+    }
+
+    // Symbol a column's boolean cells are drawn as: the one configured for its name, or else for its type.
+    pub fn symbol_for(&self, column: &Column) -> Option<Symbol> {
+        self.symbols
+            .get(&column.name)
+            .or_else(|| self.symbols.get(&column.column_type))
+            .copied()
+    }
+
+    // The keys of a group of actions as the footer shows them, the shortest key of each joined by "/".
+    // None when one of them has no key left to show.
+    #[cfg(feature = "tui")]
+    pub fn hint_keys(&self, actions: &[Action]) -> Option<String> {
+        let keys: Option<Vec<&str>> = actions
+            .iter()
+            .map(|action| {
+                let names = self.action_keys.get(action)?;
+                names
+                    .iter()
+                    .map(String::as_str)
+                    .min_by_key(|name| name.len())
+            })
+            .collect();
+        Some(keys?.join("/"))
+    }
+
+    // Whether a field's value on a row is masked, which it is for redacted fields
+    // unless that row was revealed and is still selected
+    pub fn is_redacted(&self, field: &str, row: usize) -> bool {
+        self.redact.contains(field)
+            && !(self.revealed_row == Some(row) && self.selected_row == Some(row))
+    }
+
+    // A row's values by field name with the redacted ones masked
+    pub fn redact_row<'a>(&self, map: &'a JsonMap, row: usize) -> Cow<'a, JsonMap> {
+        if !map.keys().any(|field| self.is_redacted(field, row)) {
+            return Cow::Borrowed(map);
+        }
+        let mut map = map.clone();
+        for (field, value) in &mut map {
+            if self.is_redacted(field, row) {
+                *value = JsonValue::from(REDACTED);
+            }
+        }
+        Cow::Owned(map)
+    }
+
+    // Show the redacted values of the selected row, or mask them again
+    pub fn toggle_reveal(&mut self) {
+        if self.redact.is_empty() {
+            self.notify("nothing is redacted");
+        } else if self.revealed_row.is_some() {
+            self.revealed_row = None;
+        } else if let Some(row) = self.selected_row {
+            self.revealed_row = Some(row);
+            self.notify("revealed until the selection moves");
+        } else {
+            self.notify("no row selected");
+        }
+    }
+
+    // Whether a column of the displayed document is hidden
+    fn is_hidden(&self, index: usize) -> bool {
+        self.displayed_document()
+            .columns
+            .get(index)
+            .is_some_and(|column| self.hidden_columns.contains(&column.name))
+    }
+
+    // Hide a column of the displayed document, or show it again if it is hidden
+    pub fn toggle_column(&mut self, index: usize) {
+        let Some(column) = self.displayed_document().columns.get(index) else {
+            return;
+        };
+        let name = column.name.clone();
+        if !self.hidden_columns.remove(&name) {
+            self.hidden_columns.insert(name);
+        }
+    }
+
+    // Move the column selection along with a horizontal scroll of the table, which the draw thread keeps
+    pub fn scroll_columns(&mut self, delta: isize) {
+        let last = self.displayed_document().columns.len().saturating_sub(1);
+        self.expanded = false;
+        self.selected_col = self.selected_col.saturating_add_signed(delta).min(last);
+    }
+
+    // Cycle sorting on the selected column: ascending, then descending, then back to insertion order
+    pub fn cycle_sort(&mut self) {
+        let column = self.selected_col;
+        self.sort = match self.sort {
+            Some((sorted, SortDirection::Ascending)) if sorted == column => {
+                Some((column, SortDirection::Descending))
+            }
+            Some((sorted, SortDirection::Descending)) if sorted == column => None,
+            _ => Some((column, SortDirection::Ascending)),
+        };
+    }
+
+    // Move the selection through the visible rows, stopping at the first and last ones.
+    // A selection that is not visible (or none at all) starts again from the first visible row.
+    fn move_selection(&mut self, delta: isize) {
+        let visible = self.visible_rows();
+        if visible.is_empty() {
+            return;
+        }
+        let position = match visible
+            .iter()
+            .position(|&row| Some(row) == self.selected_row)
+        {
+            Some(position) => position.saturating_add_signed(delta).min(visible.len() - 1),
+            None => 0,
+        };
+        self.selected_row = Some(visible[position]);
+        self.inspector_scroll = 0;
+    }
+
+    // Move the selection one row down, stopping at the last row
+    pub fn select_next(&mut self) {
+        self.move_selection(1);
+    }
+
+    // Move the selection one row up, stopping at the first row
+    pub fn select_previous(&mut self) {
+        self.move_selection(-1);
+    }
+
+    // Actions listed in the command palette: those whose name or description contains the query, ignoring case
+    #[cfg(feature = "tui")]
+    pub fn palette_entries(&self) -> Vec<&'static ActionInfo> {
+        let query = self.palette_query.to_lowercase();
+        ACTIONS
+            .iter()
+            .filter(|info| {
+                info.action.name().contains(&query)
+                    || info.description.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    // Select the first visible row, which the table scrolls back to the top to show
+    pub fn select_first(&mut self) {
+        if let Some(&first) = self.visible_rows().first() {
+            self.selected_row = Some(first);
+            self.inspector_scroll = 0;
+        }
+    }
+
+    // Select the last visible row, which the table scrolls to show on its bottom line
+    pub fn select_last(&mut self) {
+        if let Some(&last) = self.visible_rows().last() {
+            self.selected_row = Some(last);
+            self.inspector_scroll = 0;
+        }
+    }
+
+    // Keep the selection inside the bounds of the current document
+    pub fn clamp_selection(&mut self) {
+        let columns = self.displayed_document().columns.len();
+        self.selected_col = self.selected_col.min(columns.saturating_sub(1));
+
+        // Keep the column selection off hidden columns, preferring the next shown one
+        if self.is_hidden(self.selected_col) {
+            let selected = self.selected_col;
+            self.selected_col = (selected..columns)
+                .chain((0..selected).rev())
+                .find(|&i| !self.is_hidden(i))
+                .unwrap_or(selected);
+        }
+
+        let rows = self.displayed_document().values.len();
+        self.selected_row = match self.selected_row {
+            Some(_) if rows == 0 => None,
+            Some(row) => Some(row.min(rows - 1)),
+            None => None,
+        };
+    }
+
+    // Note how a poll into a channel went, redrawing when it is the channel on screen
+    fn record_poll(&mut self, channel: &str, error: Option<String>) {
+        let status = PollStatus {
+            at: Instant::now(),
+            error,
+        };
+        self.polls.insert(channel.to_string(), status);
+        if channel == self.channel {
+            self.request_redraw();
+        }
+    }
+
+    // Show a transient message in the status bar
+    pub fn notify(&mut self, message: impl Into<String>) {
+        self.status_message = Some((message.into(), Instant::now()));
+        self.request_redraw();
+    }
+
+    // Raise an alert for the first rule the document matches, replacing any alert not yet acknowledged
+    fn check_alerts(&mut self, log: &Log) {
+        let Some(rule) = self.alert_rules.iter().find(|rule| rule.matches(log)) else {
+            return;
+        };
+        tracing::info!(field = %rule.field, value = %rule.equals, "alert raised");
+        self.alert = Some(Alert {
+            rule: rule.clone(),
+            raised: Instant::now(),
+        });
+        self.alerts_raised += 1;
+    }
+
+    // Clear the raised alert
+    pub fn acknowledge_alert(&mut self) {
+        self.alert = None;
+    }
+
+    // The status bar message, if it has not expired yet
+    pub fn active_status_message(&self) -> Option<&str> {
+        self.status_message
+            .as_ref()
+            .filter(|(_, set)| set.elapsed() < STATUS_MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+
+    // The selected row as a JSON object keyed by column name
+    pub fn selected_row_json(&self) -> Option<String> {
+        let document = self.displayed_document();
+        let index = self.selected_row?;
+        let row = document.values.get(index)?;
+        let object: serde_json::Map<String, JsonValue> = document
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| {
+                let value = if self.is_redacted(&column.name, index) {
+                    JsonValue::from(REDACTED)
+                } else {
+                    value.clone()
+                };
+                (column.name.clone(), value)
+            })
+            .collect();
+        serde_json::to_string_pretty(&object).ok()
+    }
+
+    // Drop every received document, on every channel, and return to the empty startup view.
+    // The document and row counters keep counting since startup, while the took statistics start over.
+    pub fn clear(&mut self) {
+        self.has_data = false;
+        self.fingerprints.clear();
+        self.current_document = Log::new();
+        self.live_document = Log::new();
+        self.display_pending = false;
+        self.mapped_document = vec![];
+        self.column_types = HashMap::new();
+        self.history.clear();
+        self.channels.clear();
+        self.type_warnings.clear();
+        self.counters.clear();
+        self.history_cursor = None;
+        self.paused = false;
+        self.snapshot = None;
+        self.search.hits.clear();
+        self.selected_row = None;
+        self.inspector_scroll = 0;
+        self.follow_feed();
+        self.clamp_selection();
+        self.request_redraw();
+    }
+
+    // The run_search method scans every document in history for rows whose field matches the search query.
+    // A query of the form field:text searches that field, anything else searches the selected column.
+    // Matching is a case-insensitive substring test unless exact matching is on, which compares whole values.
+    pub fn run_search(&mut self) {
+        let (field, query) = match self.search.query.split_once(':') {
+            Some((field, query)) => (field.to_string(), query.to_string()),
+            None => {
+                let columns = &self.displayed_document().columns;
+                let field = columns
+                    .get(self.selected_col)
+                    .map(|column| column.name.clone());
+                (field.unwrap_or_default(), self.search.query.clone())
+            }
+        };
+        let needle = query.to_lowercase();
+
+        let mut hits = vec![];
+        for history_index in 0..self.history_len() {
+            let Some(log) = self.get_historical(history_index) else {
+                continue;
+            };
+            if !self.in_time_range(log) {
+                continue;
+            }
+            for (row, map) in map_document(log).iter().enumerate() {
+                let Some(value) = map.get(&field) else {
+                    continue;
+                };
+                let text = value_text(value);
+                let matched = if self.search.exact {
+                    text == query
+                } else {
+                    text.to_lowercase().contains(&needle)
+                };
+                if matched {
+                    hits.push(SearchHit {
+                        history_index,
+                        row,
+                        value: if self.redact.contains(&field) {
+                            REDACTED.to_string()
+                        } else {
+                            text
+                        },
+                    });
+                }
+            }
+        }
+
+        self.search.field = field;
+        self.search.hits = hits;
+        self.search.selected = 0;
+    }
+
+    // Show the highlighted search result by going to its history entry and selecting its row
+    pub fn open_search_result(&mut self) {
+        let Some(hit) = self.search.hits.get(self.search.selected) else {
+            return;
+        };
+        self.history_cursor = Some(hit.history_index);
+        self.selected_row = Some(hit.row);
+        self.inspector_scroll = 0;
+        self.clamp_selection();
+    }
+
+    // Send a document to every WebSocket client, serializing it only when somebody is listening
+    fn broadcast_log(&self, log: &Log) {
+        if self.updates.receiver_count() == 0 {
+            return;
+        }
+        match serde_json::to_string(log) {
+            Ok(json) => {
+                // Sending only fails when every client disconnected in the meantime
+                let _ = self.updates.send(json.into());
+            }
+            Err(e) => tracing::error!(error = %e, "failed to serialize streamed document"),
+        }
+    }
+
+    // Append a document to the output file as one JSON line. The writer is buffered and
+    // flushed periodically by flush_thread, so this does not touch the disk on every request.
+    fn persist_log(&mut self, log: &Log) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        let result = serde_json::to_writer(&mut *output, log)
+            .map_err(io::Error::from)
+            .and_then(|_| output.write_all(b"\n"));
+        if let Err(e) = result {
+            tracing::error!(error = %e, "failed to write document to output file");
+            self.last_error = Some(format!("failed to write output: {e}"));
+        }
+    }
+
+    // Flush buffered output to disk
+    pub fn flush_output(&mut self) {
+        if let Some(Err(e)) = self.output.as_mut().map(Write::flush) {
+            tracing::error!(error = %e, "failed to flush output file");
+            self.last_error = Some(format!("failed to flush output: {e}"));
+        }
+    }
+
+    // Take in a document posted to a channel. Every document is counted, persisted, streamed and checked
+    // against the alert rules, then either becomes the current log and is mapped, when its channel is on screen,
+    // or is put away with its channel until that is switched to.
+    pub fn update_log(&mut self, channel: &str, mut new_log: Log) {
+        self.has_data = true;
+        new_log.normalize_timestamps();
+        if self.redact_mode == RedactMode::Drop {
+            new_log = new_log.replaced(&self.redact, JsonValue::Null).into_owned();
+        }
+        new_log.type_cells();
+        new_log.received = Some(Instant::now());
+        self.last_update = new_log.received;
+
+        // A repeat of the channel's latest document only shows that data is still flowing
+        if self.dedup {
+            let fingerprint = new_log.fingerprint(self.dedup_key.as_deref());
+            if self.fingerprints.insert(channel.to_string(), fingerprint) == Some(fingerprint) {
+                self.counters.record_duplicate();
+                self.request_redraw();
+                return;
+            }
+        }
+        self.counters.record(&new_log, channel == self.channel);
+        self.persist_log(&new_log);
+        self.broadcast_log(&new_log);
+        self.check_alerts(&new_log);
+        if let Some(received) = &self.received {
+            let _ = received.try_send(new_log.clone());
+        }
+
+        if channel != self.channel {
+            let stored = self
+                .channels
+                .entry(channel.to_string())
+                .or_insert_with(Channel::new);
+            push_bounded(&mut stored.history, self.history_capacity, new_log.clone());
+            stored.current_document = new_log;
+            self.request_redraw();
+            return;
+        }
+
+        self.push_log(new_log.clone());
+        self.current_document = new_log;
+
+        // A feed scrolled up keeps its place while newer documents arrive below it
+        if !self.follow {
+            self.feed_scroll = (self.feed_scroll + 1).min(self.history_len() - 1);
+        }
+
+        // During a burst the screen moves on at most once per debounce window,
+        // and the draw thread catches up with the latest document once the window has passed
+        if self.last_shown.elapsed() >= self.debounce {
+            self.show_latest();
+        } else {
+            self.display_pending = true;
+        }
+
+        // Keep showing the same history entry if one is being viewed
+        let oldest = self.history_len() - 1;
+        self.history_cursor = self.history_cursor.map(|index| (index + 1).min(oldest));
+
+        // Search results point into history too, so they shift along and drop off with it
+        let len = self.history_len();
+        self.search.hits.retain_mut(|hit| {
+            hit.history_index += 1;
+            hit.history_index < len
+        });
+        self.search.selected = self
+            .search
+            .selected
+            .min(self.search.hits.len().saturating_sub(1));
+
+        self.expire_history();
+        self.clamp_selection();
+        self.request_redraw();
+    }
+
+    // Evict documents older than the history TTL, the oldest first, from every channel's history.
+    // The history entry being viewed and everything newer than it are kept, so nothing is pulled out
+    // from under the screen, and search hits on evicted entries drop off with them.
+    pub fn expire_history(&mut self) {
+        let Some(ttl) = self.history_ttl else {
+            return;
+        };
+        let expired = |log: &Log| log.received.is_some_and(|at| at.elapsed() >= ttl);
+        for channel in self.channels.values_mut() {
+            while channel.history.front().is_some_and(expired) {
+                channel.history.pop_front();
+            }
+        }
+
+        let viewed = self
+            .history_cursor
+            .map(|index| self.history.len() - 1 - index);
+        let mut evicted = 0;
+        while self.history.front().is_some_and(expired) && viewed.is_none_or(|at| evicted < at) {
+            self.history.pop_front();
+            evicted += 1;
+        }
+        if evicted == 0 {
+            return;
+        }
+        let len = self.history.len();
+        self.search.hits.retain(|hit| hit.history_index < len);
+        self.search.selected = self
+            .search
+            .selected
+            .min(self.search.hits.len().saturating_sub(1));
+        self.request_redraw();
+    }
+
+    // Whether the document on screen is older than the history TTL, and so would have been evicted
+    // were it not being shown
+    pub fn is_expired(&self) -> bool {
+        let received = self.displayed_document().received;
+        self.history_ttl
+            .zip(received)
+            .is_some_and(|(ttl, at)| at.elapsed() >= ttl)
+    }
+}
+
+// List every value of a document that does not fit its column's declared type, naming its row and column.
+// An @timestamp that could not be parsed is listed whatever its column's type.
+// The value of a redacted field is written as the mask, so the panel does not give it away.
+
+fn type_warnings(log: &Log, redact: &HashSet<String>, ascii: bool) -> Vec<String> {
+    let mut warnings = vec![];
+    for (row, values) in log.values.iter().enumerate() {
+        for (column, value) in log.columns.iter().zip(values) {
+            let expected = if column.name == TIMESTAMP {
+                "timestamp"
+            } else {
+                &column.column_type
+            };
+            let valid = match expected {
+                "timestamp" => value.is_null() || parse_timestamp(value).is_some(),
+                column_type => matches_type(value, column_type),
+            };
+            if !valid {
+                let shown = if redact.contains(&column.name) {
+                    REDACTED.to_string()
+                } else {
+                    truncate(value.to_string(), MAX_COLUMN_WIDTH.into(), ascii)
+                };
+                warnings.push(format!(
+                    "row {}, {}: {shown} is not a valid {expected}",
+                    row + 1,
+                    column.name,
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+// Whether a value fits a column type. Nulls fit every type, multi-valued fields fit when each of their
+// values does, and types without a simple JSON form, such as objects or geo points, accept anything.
+
+fn matches_type(value: &JsonValue, column_type: &str) -> bool {
+    match (column_type, value) {
+        (_, JsonValue::Null) => true,
+        (_, JsonValue::Array(values)) => {
+            values.iter().all(|value| matches_type(value, column_type))
+        }
+        ("long" | "integer" | "short" | "byte" | "unsigned_long", value) => {
+            value.is_i64() || value.is_u64()
+        }
+        (column_type, value) if NUMERIC_TYPES.contains(&column_type) => value.is_number(),
+        ("keyword" | "text" | "ip" | "version" | "constant_keyword" | "wildcard", value) => {
+            value.is_string()
+        }
+        ("boolean", value) => value.is_boolean(),
+        ("date" | "date_nanos", value) => parse_timestamp(value).is_some(),
+        _ => true,
+    }
+}
+
+// Append a document to a history, evicting the oldest one when it already holds capacity documents
+
+fn push_bounded(history: &mut VecDeque<Log>, capacity: usize, log: Log) {
+    if history.len() >= capacity {
+        history.pop_front();
+    }
+    history.push_back(log);
+}
+
+// Create the bounded channel documents are streamed through
+
+fn stream_channel() -> broadcast::Sender<Arc<str>> {
+    broadcast::channel(STREAM_CAPACITY).0
+}
+
+// Map the columns of a log to their respective values, one map for each of its rows
+
+pub fn map_document(log: &Log) -> Vec<JsonMap> {
+    (0..log.values.len())
+        .map(|index| map_row(log, index))
+        .collect()
+}
+
+// Map the columns of a log to their values in the given row, which is empty when the row does not exist
+
+pub fn map_row(log: &Log, index: usize) -> JsonMap {
+    let mut map = HashMap::new();
+    for (i, column) in log.columns.iter().enumerate() {
+        if let Some(value) = log.value(index, i) {
+            map.insert(column.name.clone(), value.clone());
+        }
+    }
+    map
+}
+
+// Project every row of a document down to the given fields, found as field_value finds them.
+// Fields a row lacks are left out, or given as null when nulls is set.
+
+fn project_rows(
+    log: &Log,
+    fields: &[&str],
+    nulls: bool,
+) -> Vec<serde_json::Map<String, JsonValue>> {
+    map_document(log)
+        .iter()
+        .map(|row| {
+            fields
+                .iter()
+                .filter_map(|&field| match field_value(row, field) {
+                    Some(value) => Some((field.to_string(), value.clone())),
+                    None if nulls => Some((field.to_string(), JsonValue::Null)),
+                    None => None,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Check whether any cell of a row contains the (already lowercased) query
+
+fn row_matches(row: &[JsonValue], query: &str) -> bool {
+    row.iter()
+        .any(|value| value_text(value).to_lowercase().contains(query))
+}
+
+// Order two cells of the sort column by their typed values, with rows too short to have the column sorted last
+
+fn compare_cells(a: Option<&TypedCell>, b: Option<&TypedCell>) -> cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.compare(b),
+        (Some(_), None) => cmp::Ordering::Less,
+        (None, Some(_)) => cmp::Ordering::Greater,
+        (None, None) => cmp::Ordering::Equal,
+    }
+}
+
+// Map the columns of a log to their types, as Log::column_type gives them
+
+pub fn map_column_types(log: &Log) -> HashMap<String, String> {
+    (log.columns.iter().enumerate())
+        .map(|(i, column)| (column.name.clone(), log.column_type(i).to_string()))
+        .collect()
+}
+
+// The rows of a document as objects keyed by column name, with keys sorted so the output is stable
+// between runs. A document without rows maps to no objects.
+
+pub fn mapped_rows(log: &Log) -> Vec<BTreeMap<String, JsonValue>> {
+    map_document(log)
+        .into_iter()
+        .map(|row| row.into_iter().collect())
+        .collect()
+}
+
+// The flush_thread function flushes the buffered output file on a fixed interval,
+// so documents reach the disk regularly without a write call on every request.
+
+pub async fn flush_thread(app_state: SharedAppState) {
+    let mut interval = tokio::time::interval(OUTPUT_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        app_state.write().unwrap().flush_output();
+    }
+}
+
+// The expire_thread function sweeps documents past the history TTL out of history on a fixed
+// interval, so they leave even while nothing is posted and no dashboard is drawn, as under --headless.
+
+pub async fn expire_thread(app_state: SharedAppState) {
+    let mut interval = tokio::time::interval(HISTORY_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        app_state.write().unwrap().expire_history();
+    }
+}
+
+// The server_thread function is responsible for handling incoming HTTP requests.
+// It takes a shared application state and where to listen, and runs an asynchronous server using Warp
+// over the filters built by the routes function.
+// The server listens on the TCP address, over HTTPS when a certificate and key are given, or on the
+// Unix socket bound by main, and runs indefinitely until the application is terminated.
+
+pub async fn server_thread(
+    app_state_server: SharedAppState,
+    listen: Listen,
+    auth_token: Option<String>,
+    max_body_bytes: u64,
+    rate_limit: Option<u32>,
+) {
+    // Record where the server listens for "/info"
+    {
+        let mut state = app_state_server.write().unwrap();
+        (state.info.address, state.info.tls) = match &listen {
+            Listen::Tcp(address) => (address.to_string(), false),
+            Listen::Tls(address, ..) => (address.to_string(), true),
+            Listen::Unix(listener) => (
+                listener
+                    .local_addr()
+                    .ok()
+                    .and_then(|address| {
+                        address.as_pathname().map(|path| path.display().to_string())
+                    })
+                    .unwrap_or_default(),
+                false,
+            ),
+        };
+    }
+
+    // Start the server
+    let server = warp::serve(routes(
+        app_state_server.clone(),
+        auth_token,
+        max_body_bytes,
+        rate_limit,
+    ));
+    let (address, error) = match listen {
+        Listen::Tcp(address) => match server.try_bind_ephemeral(address) {
+            Ok((_, running)) => return running.await,
+            Err(e) => (address, e),
+        },
+        Listen::Tls(address, cert, key) => {
+            let server = server.tls().cert_path(cert).key_path(key);
+            match server.try_bind_with_graceful_shutdown(address, future::pending()) {
+                Ok((_, running)) => return running.await,
+                Err(e) => (address, e),
+            }
+        }
+        Listen::Unix(listener) => {
+            return server.run_incoming(UnixListenerStream::new(listener)).await;
+        }
+    };
+
+    // Say why nothing is arriving rather than leaving the screen waiting
+    tracing::error!(%address, error = %error, "failed to bind server");
+    let mut state = app_state_server.write().unwrap();
+    state.server_error = Some(format!("failed to bind {address}: {error}"));
+    state.request_redraw();
+}
+
+// The start_server function runs the ingest server on a plain TCP address with the defaults the
+// binary uses, no token, the default body limit and no rate limit, for embedding the viewer's
+// ingestion in another program. Documents land in the given state as they would for the TUI.
+
+pub async fn start_server(app_state: SharedAppState, address: SocketAddrV4) {
+    server_thread(app_state, Listen::Tcp(address), None, MAX_BODY_BYTES, None).await
+}
+
+// The poll_thread function pulls data from Elasticsearch instead of waiting for it to be pushed.
+// Every interval it POSTs the SQL query to the cluster, with basic auth when credentials are given,
+// and feeds the response into the given channel. Each query has a task of its own, so one failing
+// leaves the others polling. A failed request is recorded against the channel for the status bar
+// and simply retried on the next tick, and the next poll that succeeds clears it.
+
+pub async fn poll_thread(
+    app_state: SharedAppState,
+    url: Url,
+    channel: String,
+    query: String,
+    interval: Duration,
+    credentials: Option<(String, Option<String>)>,
+) {
+    let client = reqwest::Client::new();
+    let body = serde_json::json!({ "query": query });
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        let mut request = client.post(url.clone()).json(&body).timeout(interval);
+        if let Some((user, pass)) = &credentials {
+            request = request.basic_auth(user, pass.as_ref());
+        }
+        let result = async {
+            request
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Log>()
+                .await
+        };
+
+        match result.await {
+            Ok(log) => {
+                tracing::info!(channel, rows = log.values.len(), "poll succeeded");
+                let mut state = app_state.write().unwrap();
+                state.record_poll(&channel, None);
+                state.update_log(&channel, log);
+            }
+            Err(e) => {
+                tracing::warn!(channel, error = %e, "poll failed");
+                let mut state = app_state.write().unwrap();
+                state.record_poll(&channel, Some(e.to_string()));
+                state.request_redraw();
+            }
+        }
+    }
+}
+
+// The routes function builds every HTTP route the server answers, sharing the application state between them.
+// A POST to "/data/<channel>" updates that channel with the new log and responds with its current document,
+// or with that document's mapped rows when "?format=mapped" is given,
+// and a GET to "/data/<channel>" returns the channel's current document as-is, which is handy for scripting
+// and debugging. A bare "/data" stands for the default channel.
+// A POST to "/data/search/<channel>" takes an Elasticsearch _search response instead, whose hits become the rows.
+// Adding "?fields=a,b" to the GET returns just those fields of each mapped row instead.
+// A POST to "/bulk" takes newline-delimited Log objects and ingests each valid line as it streams in.
+// A POST to "/clear" wipes the current document and history.
+// A GET to "/history/<channel>" returns the channel's history newest first, a page at a time with "?limit=N&offset=M",
+// and its full length in an X-Total-Count header.
+// A GET to "/schema" returns a JSON Schema of the documents "/data" accepts.
+// A GET to "/health" is a cheap liveness probe that reports how many documents have been received
+// without touching anything else.
+// A GET to "/info" reports the version, where the server listens, the configured fields, the history capacity
+// and which of TLS, auth and persistence are on, for inventorying running instances.
+// When an auth token is given the three write routes require it as a bearer token, while reads stay open.
+// Bodies posted to "/data" and lines posted to "/bulk" are capped at max_body_bytes, and with a rate limit posts to "/data"
+// beyond that many per second are turned away before their body is read.
+// Rejections are passed through handle_rejection so malformed bodies get a descriptive 400.
+// The filters are public so they can be mounted in another warp server or driven by warp::test.
+
+pub fn routes(
+    app_state: SharedAppState,
+    auth_token: Option<String>,
+    max_body_bytes: u64,
+    rate_limit: Option<u32>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let auth_required = auth_token.is_some();
+    let auth = authorized(auth_token.map(Arc::from));
+    let limiter = rate_limit.map(|rate| Arc::new(Mutex::new(RateLimiter::new(rate))));
+    // The probes read the counters alone, so they never wait on the application state's lock
+    let counters = app_state.read().unwrap().counters.clone();
+
+    // Define the route for receiving logs, leaving "/data/search" to the search route
+    let app_state_logs = app_state.clone();
+    let limiter_logs = limiter.clone();
+    let logs_route = warp::post()
+        .and(warp::path("data"))
+        .and(channel_path().and_then(|channel: String| async move {
+            match channel.as_str() {
+                SEARCH_PATH => Err(warp::reject::not_found()),
+                _ => Ok(channel),
+            }
+        }))
+        .and(auth.clone())
+        .and(rate_limited(limiter.clone()))
+        .and(warp::query::<PostOptions>())
+        .and(document_body::<Posted>(max_body_bytes))
+        .and_then(
+            move |channel: String,
+                  options: PostOptions,
+                  request_id: String,
+                  format: BodyFormat,
+                  posted: Posted| {
+                let app_state = app_state_logs.clone();
+                let limiter = limiter_logs.clone();
+                async move {
+                    let reply = match posted {
+                        Posted::One(log) => {
+                            ingest_document(&app_state, channel, options, &request_id, format, log)
+                        }
+                        Posted::Many(_) if options.format == ResponseFormat::Mapped => {
+                            return Err(warp::reject::custom(RejectedBody {
+                                request_id,
+                                reason: BodyRejection::MappedArray,
+                            }));
+                        }
+                        Posted::Many(logs) => {
+                            // The rate limit counts documents, and the request itself paid for the first
+                            if let Some(limiter) = &limiter {
+                                limiter.lock().unwrap().charge(logs.len().saturating_sub(1));
+                            }
+                            ingest_documents(&app_state, &channel, &request_id, format, logs)
+                        }
+                    };
+                    Ok(warp::reply::with_header(
+                        reply,
+                        REQUEST_ID_HEADER,
+                        request_id,
+                    ))
+                }
+            },
+        );
+
+    // Define the route for receiving _search responses, turned into logs
+    let app_state_search = app_state.clone();
+    let search_route = warp::post()
+        .and(warp::path("data"))
+        .and(warp::path(SEARCH_PATH))
+        .and(channel_path())
+        .and(auth.clone())
+        .and(rate_limited(limiter))
+        .and(warp::query::<PostOptions>())
+        .and(document_body::<HitsResponse>(max_body_bytes))
+        .map(
+            move |channel: String,
+                  options: PostOptions,
+                  request_id: String,
+                  format: BodyFormat,
+                  response: HitsResponse| {
+                let log = Log::from(response);
+                let reply = ingest_document(
+                    &app_state_search,
+                    channel,
+                    options,
+                    &request_id,
+                    format,
+                    log,
+                );
+                warp::reply::with_header(reply, REQUEST_ID_HEADER, request_id)
+            },
+        );
+
+    // Define the route for bulk loading newline-delimited logs
+    let app_state_bulk = app_state.clone();
+    let bulk_route = warp::post()
+        .and(warp::path("bulk"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(warp::body::stream())
+        .then(move |body| {
+            let app_state = app_state_bulk.clone();
+            async move {
+                let summary = ingest_ndjson(app_state, body, max_body_bytes).await;
+                tracing::info!(
+                    ingested = summary.ingested,
+                    documents = summary.documents,
+                    errors = summary.errors,
+                    truncated = summary.truncated,
+                    "bulk load received"
+                );
+                // A body that broke off is an error even though the lines before the break were kept
+                let status = if summary.truncated {
+                    StatusCode::BAD_REQUEST
+                } else {
+                    StatusCode::OK
+                };
+                warp::reply::with_status(warp::reply::json(&summary), status)
+            }
+        });
+
+    // Define the route for resetting the dashboard
+    let app_state_clear = app_state.clone();
+    let clear_route = warp::post()
+        .and(warp::path("clear"))
+        .and(warp::path::end())
+        .and(auth)
+        .map(move || {
+            let mut state = app_state_clear.write().unwrap();
+            state.clear();
+            tracing::info!("state cleared");
+            warp::reply::json(&state.current_document)
+        });
+
+    // Define the route for fetching the current document
+    let app_state_current = app_state.clone();
+    let current_route = warp::get()
+        .and(warp::path("data"))
+        .and(channel_path())
+        .and(warp::query::<DataQuery>())
+        .map(move |channel: String, query: DataQuery| {
+            let state = app_state_current.read().unwrap();
+            match (state.channel_document(&channel), &query.fields) {
+                (Some(document), None) => warp::reply::json(document).into_response(),
+                (Some(document), Some(fields)) => {
+                    let fields: Vec<&str> = fields.split(',').map(str::trim).collect();
+                    warp::reply::json(&project_rows(document, &fields, query.nulls)).into_response()
+                }
+                // The default channel always exists, so only named channels can be unknown
+                (None, _) => error_reply(
+                    StatusCode::NOT_FOUND,
+                    "unknown channel",
+                    format!("nothing has been posted to channel {channel}"),
+                ),
+            }
+        });
+
+    // Define the route for fetching a channel's history, newest first.
+    // Only the requested page is copied under the lock, so a large dump does not hold up ingestion while it is serialized
+    let app_state_history = app_state.clone();
+    let history_route = warp::get()
+        .and(warp::path("history"))
+        .and(channel_path())
+        .and(warp::query::<HistoryQuery>())
+        .map(move |channel: String, query: HistoryQuery| {
+            let page = {
+                let state = app_state_history.read().unwrap();
+                state.channel_history(&channel).map(|history| {
+                    let logs: Vec<Log> = history
+                        .iter()
+                        .rev()
+                        .skip(query.offset)
+                        .take(query.limit.unwrap_or(usize::MAX))
+                        .cloned()
+                        .collect();
+                    (history.len(), logs)
+                })
+            };
+            match page {
+                Some((total, logs)) => {
+                    let reply = warp::reply::json(&logs);
+                    warp::reply::with_header(reply, "x-total-count", total.to_string())
+                        .into_response()
+                }
+                None => error_reply(
+                    StatusCode::NOT_FOUND,
+                    "unknown channel",
+                    format!("nothing has been posted to channel {channel}"),
+                ),
+            }
+        });
+
+    // Define the route for streaming received documents over a WebSocket
+    let app_state_stream = app_state.clone();
+    let stream_route = warp::path("stream")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(warp::query::<StreamOptions>())
+        .map(move |ws: warp::ws::Ws, options: StreamOptions| {
+            // Subscribe while holding the lock so nothing between the snapshot and the stream is missed
+            let state = app_state_stream.read().unwrap();
+            let updates = state.updates.subscribe();
+            let current = options
+                .current
+                .then(|| serde_json::to_string(&state.current_document).ok())
+                .flatten();
+            ws.on_upgrade(move |socket| stream_client(socket, updates, current))
+        });
+
+    // Define the route for scraping Prometheus metrics
+    let counters_metrics = counters.clone();
+    let metrics_route = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .map(move || {
+            let metrics = render_metrics(&counters_metrics);
+            warp::reply::with_header(metrics, "content-type", "text/plain; version=0.0.4")
+        });
+
+    // Define the route describing the document format
+    let schema_route = warp::get()
+        .and(warp::path("schema"))
+        .and(warp::path::end())
+        .map(|| warp::reply::json(&log_schema()));
+
+    // Define the route reporting the instance's settings
+    let app_state_info = app_state.clone();
+    let info_route = warp::get()
+        .and(warp::path("info"))
+        .and(warp::path::end())
+        .map(move || {
+            let state = app_state_info.read().unwrap();
+            warp::reply::json(&InfoReply {
+                version: env!("CARGO_PKG_VERSION"),
+                address: &state.info.address,
+                fields: &state.info.fields,
+                history_capacity: state.history_capacity,
+                features: Features {
+                    tls: state.info.tls,
+                    auth: auth_required,
+                    persistence: state.output.is_some(),
+                },
+            })
+        });
+
+    // Define the liveness probe
+    let health_route = warp::get()
+        .and(warp::path("health"))
+        .and(warp::path::end())
+        .map(move || {
+            warp::reply::json(&Health {
+                status: "ok",
+                documents_received: counters.documents(),
+                took: counters.took_stats(),
+            })
+        });
+
+    logs_route
+        .or(search_route)
+        .or(bulk_route)
+        .or(clear_route)
+        .or(current_route)
+        .or(history_route)
+        .or(stream_route)
+        .or(metrics_route)
+        .or(schema_route)
+        .or(info_route)
+        .or(health_route)
+        .recover(handle_rejection)
+        .with(warp::trace::request())
+}
+
+// The stream_client function feeds one WebSocket client, starting with the current document when it asked for it.
+// Each received document is sent as a JSON text message until the client goes away. A client that falls
+// further behind than the channel holds is closed with a policy violation rather than left to miss documents.
+
+async fn stream_client(
+    socket: WebSocket,
+    mut updates: broadcast::Receiver<Arc<str>>,
+    current: Option<String>,
+) {
+    let (mut sink, mut incoming) = socket.split();
+    if let Some(current) = current {
+        if sink.send(Message::text(current)).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(document) => {
+                    if sink.send(Message::text(&*document)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "dropping lagging stream client");
+                    let _ = sink.send(Message::close_with(1008u16, "lagging")).await;
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // Incoming messages are ignored, the client is only watched for disconnecting
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+        }
+    }
+}
+
+// The render_metrics function formats the ingestion counters in the Prometheus text exposition format.
+// The totals count since startup and survive a clear, while the took gauge follows the current document.
+
+fn render_metrics(counters: &Counters) -> String {
+    format!(
+        "# HELP dashview_documents_total Documents received since startup.
+# TYPE dashview_documents_total counter
+dashview_documents_total {}
+# HELP dashview_rows_total Rows received across all documents since startup.
+# TYPE dashview_rows_total counter
+dashview_rows_total {}
+# HELP dashview_duplicates_total Documents left out of history as repeats of the latest one.
+# TYPE dashview_duplicates_total counter
+dashview_duplicates_total {}
+# HELP dashview_last_took_ms Query latency reported by Elasticsearch for the current document.
+# TYPE dashview_last_took_ms gauge
+dashview_last_took_ms {}
+",
+        counters.documents(),
+        counters.rows(),
+        counters.duplicates(),
+        counters.current_took(),
+    )
+}
+
+// The authorized filter lets a request through when it carries "Authorization: Bearer <token>" matching
+// the configured token, compared in constant time, and rejects it with Unauthorized otherwise.
+// Without a configured token every request passes.
+
+fn authorized(
+    token: Option<Arc<str>>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                let Some(token) = token else {
+                    return Ok(());
+                };
+                let given = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "));
+                match given {
+                    Some(given) if bool::from(given.as_bytes().ct_eq(token.as_bytes())) => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+// The rate_limited filter takes a token from the shared bucket for each request and rejects the request with
+// RateLimited when the bucket is empty. Without a limiter every request passes.
+
+fn rate_limited(
+    limiter: Option<Arc<Mutex<RateLimiter>>>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            let limiter = limiter.clone();
+            async move {
+                let Some(limiter) = limiter else {
+                    return Ok(());
+                };
+                let acquired = limiter.lock().unwrap().try_acquire();
+                acquired.map_err(|retry_after| warp::reject::custom(RateLimited { retry_after }))
+            }
+        })
+        .untuple_one()
+}
+
+// The log_schema function describes the Log and Column structures as a JSON Schema, written by hand
+// to match how they deserialize: "rows" is accepted in place of "values", and only the rows are required.
+
+fn log_schema() -> JsonValue {
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Log",
+        "description": "An ES|QL or ES SQL query response",
+        "type": "object",
+        "properties": {
+            "values": {
+                "description": "Rows of the response, each holding one value per column",
+                "type": "array",
+                "items": {"type": "array"}
+            },
+            "rows": {
+                "description": "Alias of values used by ES SQL responses",
+                "type": "array",
+                "items": {"type": "array"}
+            },
+            "took": {
+                "description": "Milliseconds Elasticsearch took to run the query, 0 when left out",
+                "type": "integer",
+                "minimum": 0
+            },
+            "columns": {
+                "description": "Names and types of the columns, in the order of the values in each row",
+                "type": "array",
+                "items": {"$ref": "#/$defs/Column"}
+            },
+            "cursor": {
+                "description": "Cursor of an ES SQL response with more pages",
+                "type": "string"
+            }
+        },
+        "oneOf": [
+            {"required": ["values"]},
+            {"required": ["rows"]}
+        ],
+        "$defs": {
+            "Column": {
+                "type": "object",
+                "properties": {
+                    "name": {"description": "Field name", "type": "string"},
+                    "type": {"description": "Elasticsearch field type, such as keyword or long", "type": "string"}
+                },
+                "required": ["name", "type"]
+            }
+        }
+    })
+}
+
+// Store a posted document on its channel and reply with the channel's document, raw or as mapped rows,
+// in the given format
+
+fn ingest_document(
+    app_state: &SharedAppState,
+    channel: String,
+    options: PostOptions,
+    request_id: &str,
+    format: BodyFormat,
+    log: Log,
+) -> warp::reply::Response {
+    tracing::info!(
+        request_id,
+        channel,
+        rows = log.values.len(),
+        took = log.took,
+        columns = log.columns.len(),
+        "document received"
+    );
+    let mut state = app_state.write().unwrap();
+    state.update_log(&channel, log);
+    let document = state
+        .channel_document(&channel)
+        .map(|document| document.redacted(&state.redact));
+    match options.format {
+        ResponseFormat::Raw => format.reply(&document),
+        ResponseFormat::Mapped => format.reply(&document.map(|document| mapped_rows(&document))),
+    }
+}
+
+// Store an array of posted documents on a channel in order, leaving the last on screen, and reply with how many
+// there were and how many rows they held
+
+fn ingest_documents(
+    app_state: &SharedAppState,
+    channel: &str,
+    request_id: &str,
+    format: BodyFormat,
+    logs: Vec<Log>,
+) -> warp::reply::Response {
+    tracing::info!(
+        request_id,
+        channel,
+        documents = logs.len(),
+        "documents received"
+    );
+    let mut state = app_state.write().unwrap();
+    let mut summary = BulkSummary::default();
+    for log in logs {
+        summary.ingested += log.values.len();
+        summary.documents += 1;
+        state.update_log(channel, log);
+    }
+    format.reply(&summary)
+}
+
+// The channel_path filter extracts the channel named by the rest of the path, "/<channel>",
+// or the default channel when the path ends there
+
+fn channel_path() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path::param::<String>()
+        .and(warp::path::end())
+        .or(warp::path::end().map(|| DEFAULT_CHANNEL.to_string()))
+        .unify()
+}
+
+// The document_body filter generates an ID for the request and extracts it with a Log, or another document,
+// from the request body, decompressing it first when it is sent with Content-Encoding: gzip.
+// The body is CBOR or MessagePack when its Content-Type says so and JSON otherwise, including without the header.
+// The format to reply in comes out too: the body's own when Accept asks for it, JSON in every other case.
+// A body whose Content-Length is over the limit is refused before any of it is read, one sent chunked without
+// the header as soon as more than the limit has arrived, and a gzip body as soon as it decompresses past it.
+// Anything that cannot be decoded is rejected with a RejectedBody for handle_rejection to report along with the ID.
+
+fn document_body<T: DeserializeOwned + Send>(
+    limit: u64,
+) -> impl Filter<Extract = (String, BodyFormat, T), Error = warp::Rejection> + Clone {
+    warp::any()
+        .map(|| Uuid::new_v4().to_string())
+        .and(warp::header::optional::<u64>("content-length"))
+        .and(warp::header::optional::<String>("content-encoding"))
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::header::optional::<String>("accept"))
+        .and(warp::body::stream())
+        .and_then(
+            move |request_id: String,
+                  length: Option<u64>,
+                  encoding: Option<String>,
+                  content_type: Option<String>,
+                  accept: Option<String>,
+                  body| async move {
+                let format = BodyFormat::of_content_type(content_type.as_deref());
+                let reply = match accept {
+                    Some(accept) if format.accepted_by(&accept) => format,
+                    _ => BodyFormat::Json,
+                };
+                let document = match read_body(length, body, limit).await {
+                    Ok(body) => decode_body(encoding.as_deref(), format, &body, limit),
+                    Err(reason) => Err(reason),
+                };
+                match document {
+                    Ok(document) => Ok((request_id, reply, document)),
+                    Err(reason) => Err(warp::reject::custom(RejectedBody { request_id, reason })),
+                }
+            },
+        )
+        .untuple_one()
+}
+
+// Read a request body to its end, refusing it once it is over the limit: at once when its Content-Length says so,
+// and otherwise as soon as more than that has arrived, since a chunked body does not declare its length
+
+async fn read_body<S, B>(length: Option<u64>, body: S, limit: u64) -> Result<Vec<u8>, BodyRejection>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
+    if length.is_some_and(|length| length > limit) {
+        return Err(BodyRejection::TooLarge);
+    }
+    let mut body = pin!(body);
+    let mut read = vec![];
+    while let Some(chunk) = body.next().await {
+        let mut chunk = chunk.map_err(|e| BodyRejection::Unreadable(e.to_string()))?;
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            if (read.len() + bytes.len()) as u64 > limit {
+                return Err(BodyRejection::TooLarge);
+            }
+            read.extend_from_slice(bytes);
+            let taken = bytes.len();
+            chunk.advance(taken);
+        }
+    }
+    Ok(read)
+}
+
+// Decode a request body in the given format, decompressing it first when its encoding is gzip
+
+fn decode_body<T: DeserializeOwned>(
+    encoding: Option<&str>,
+    format: BodyFormat,
+    body: &[u8],
+    limit: u64,
+) -> Result<T, BodyRejection> {
+    let decoded = match encoding.map(str::trim) {
+        None | Some("identity") => body.to_vec(),
+        Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => {
+            // Stop decompressing just past the limit so a small body cannot expand without bound
+            let mut decoded = vec![];
+            GzDecoder::new(body)
+                .take(limit + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|e| BodyRejection::CorruptGzip(e.to_string()))?;
+            if decoded.len() as u64 > limit {
+                return Err(BodyRejection::TooLarge);
+            }
+            decoded
+        }
+        Some(encoding) => return Err(BodyRejection::UnsupportedEncoding(encoding.to_string())),
+    };
+    let document = match format {
+        BodyFormat::Json => serde_json::from_slice(&decoded).map_err(|e| e.to_string()),
+        BodyFormat::Cbor => ciborium::from_reader(decoded.as_slice()).map_err(|e| e.to_string()),
+        BodyFormat::MessagePack => rmp_serde::from_slice(&decoded).map_err(|e| e.to_string()),
+    };
+    document.map_err(BodyRejection::Malformed)
+}
+
+// The handle_rejection function turns a document body that could not be decoded into an error response:
+// a corrupt gzip stream or a body that failed to deserialize into a Log is a 400 carrying the underlying error,
+// e.g. {"error":"malformed document","detail":"missing field `values` ..."}, and an unknown encoding is a 415.
+// A write request without the auth token gets a 401 asking for a bearer token,
+// and a body over the size limit gets a 413. Errors about a body carry the request ID in the body and X-Request-Id.
+// Every other rejection, including unknown paths, is handed back to warp unchanged.
+
+async fn handle_rejection(rejection: warp::Rejection) -> Result<impl warp::Reply, warp::Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        tracing::warn!("rejected request without a valid token");
+        let reply = error_reply(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            String::from("a valid bearer token is required"),
+        );
+        return Ok(warp::reply::with_header(reply, "www-authenticate", "Bearer").into_response());
+    }
+    if let Some(RateLimited { retry_after }) = rejection.find() {
+        tracing::warn!("rejected request over the rate limit");
+        let reply = error_reply(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate limited",
+            String::from("too many documents posted, slow down"),
+        );
+        // Retry-After is in whole seconds, rounded up so a retry at that time finds a token
+        let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        return Ok(
+            warp::reply::with_header(reply, "retry-after", seconds.to_string()).into_response(),
+        );
+    }
+    let Some(RejectedBody { request_id, reason }) = rejection.find() else {
+        return Err(rejection);
+    };
+    let (status, error, detail) = match reason {
+        BodyRejection::UnsupportedEncoding(encoding) => (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported content encoding",
+            encoding.clone(),
+        ),
+        BodyRejection::CorruptGzip(detail) => {
+            (StatusCode::BAD_REQUEST, "corrupt gzip body", detail.clone())
+        }
+        BodyRejection::Malformed(detail) => (
+            StatusCode::BAD_REQUEST,
+            "malformed document",
+            detail.clone(),
+        ),
+        BodyRejection::MappedArray => (
+            StatusCode::BAD_REQUEST,
+            "unsupported format",
+            String::from("format=mapped applies to a single document, not an array"),
+        ),
+        BodyRejection::TooLarge => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "body too large",
+            String::from("the request body is over the size limit"),
+        ),
+        BodyRejection::Unreadable(detail) => {
+            (StatusCode::BAD_REQUEST, "unreadable body", detail.clone())
+        }
+    };
+    tracing::warn!(request_id, %detail, error, "rejected document");
+    let body = warp::reply::json(&ErrorReply {
+        error,
+        detail,
+        request_id: Some(request_id.clone()),
+    });
+    let reply = warp::reply::with_status(body, status);
+    Ok(warp::reply::with_header(reply, REQUEST_ID_HEADER, request_id.as_str()).into_response())
+}
+
+// Build a JSON error response with the given status
+
+fn error_reply(status: StatusCode, error: &'static str, detail: String) -> warp::reply::Response {
+    let body = warp::reply::json(&ErrorReply {
+        error,
+        detail,
+        request_id: None,
+    });
+    warp::reply::with_status(body, status).into_response()
+}
+
+// The ingest_ndjson function feeds a streamed "/bulk" body into the default channel one line at a time,
+// so memory stays bounded however large the upload is. Bytes after the last newline of a chunk are held
+// until the rest of their line arrives, and a final line without a newline is ingested when the body ends.
+// A line longer than max_line_bytes is dropped as it grows and counted as an error like one that fails to parse.
+// A body that breaks off partway keeps what was ingested before it, leaving out the unfinished line,
+// and the summary is marked truncated.
+
+async fn ingest_ndjson<S, B, E>(
+    app_state: SharedAppState,
+    body: S,
+    max_line_bytes: u64,
+) -> BulkSummary
+where
+    S: Stream<Item = Result<B, E>>,
+    B: Buf,
+    E: std::fmt::Display,
+{
+    let mut body = pin!(body);
+    let mut summary = BulkSummary::default();
+    let mut line = vec![];
+    let mut overlong = false;
+    let mut number = 1;
+
+    while let Some(chunk) = body.next().await {
+        let mut chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::warn!(error = %e, "bulk body ended early");
+                summary.truncated = true;
+                return summary;
+            }
+        };
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            let newline = bytes.iter().position(|byte| *byte == b'\n');
+            let end = newline.unwrap_or(bytes.len());
+            if !overlong {
+                line.extend_from_slice(&bytes[..end]);
+                if line.len() as u64 > max_line_bytes {
+                    line = vec![];
+                    overlong = true;
+                }
+            }
+            chunk.advance(end + usize::from(newline.is_some()));
+            if newline.is_some() {
+                ingest_line(&app_state, &line, overlong, number, &mut summary);
+                line.clear();
+                overlong = false;
+                number += 1;
+            }
+        }
+    }
+    ingest_line(&app_state, &line, overlong, number, &mut summary);
+    summary
+}
+
+// Parse one line of a bulk body and add it to the default channel, counting it in the summary.
+// Blank lines are skipped without counting.
+
+fn ingest_line(
+    app_state: &SharedAppState,
+    line: &[u8],
+    overlong: bool,
+    number: usize,
+    summary: &mut BulkSummary,
+) {
+    let line = line.trim_ascii();
+    if !overlong && line.is_empty() {
+        return;
+    }
+    let parsed = if overlong {
+        Err("line is over the size limit".to_string())
+    } else {
+        serde_json::from_slice::<Log>(line).map_err(|e| e.to_string())
+    };
+    match parsed {
+        Ok(log) => {
+            summary.ingested += log.values.len();
+            summary.documents += 1;
+            app_state.write().unwrap().update_log(DEFAULT_CHANNEL, log);
+        }
+        Err(error) => {
+            tracing::warn!(line = number, error, "skipping malformed bulk line");
+            summary.errors += 1;
+        }
+    }
+}
+
+// The key_bindings function builds the table take_input looks key presses up in. It starts from the
+// default bindings and, for every action listed in the config file, replaces that action's keys with
+// the configured ones. Specs that do not name a key are skipped and returned as warnings.
+
+#[cfg(feature = "tui")]
+pub fn key_bindings(
+    configured: &HashMap<Action, KeySpecs>,
+) -> (HashMap<KeyCode, Action>, Vec<String>) {
+    let mut bindings = HashMap::new();
+    for info in &ACTIONS {
+        if !configured.contains_key(&info.action) {
+            bindings.extend(info.keys.iter().map(|&key| (key, info.action)));
+        }
+    }
+
+    let mut warnings = vec![];
+    for (&action, specs) in configured {
+        // Name the action the way the config file does, quoted like the spec
+        let name = serde_json::to_string(&action).unwrap_or_default();
+        for spec in specs.specs() {
+            match parse_key(spec) {
+                Some(key) => {
+                    bindings.insert(key, action);
+                }
+                None => warnings.push(format!("ignoring unknown key {spec:?} bound to {name}")),
+            }
+        }
+    }
+    (bindings, warnings)
+}
+
+// Turn a key spec from the config file into a key: a single character stands for itself,
+// and special keys go by name, such as "up", "enter", "space", "pagedown" or "f5"
+
+#[cfg(feature = "tui")]
+fn parse_key(spec: &str) -> Option<KeyCode> {
+    let mut chars = spec.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    let key = match spec.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        name => {
+            let number = name.strip_prefix('f')?.parse().ok()?;
+            (1..=12).contains(&number).then_some(KeyCode::F(number))?
+        }
+    };
+    Some(key)
+}
+
+// Names of the keys bound to each action, in the spelling the config file uses, ordered so the list is stable.
+// The command palette can always be opened with Ctrl-P as well.
+
+#[cfg(feature = "tui")]
+pub fn action_keys(bindings: &HashMap<KeyCode, Action>) -> HashMap<Action, Vec<String>> {
+    let mut keys: HashMap<Action, Vec<String>> = HashMap::new();
+    for (&key, &action) in bindings {
+        keys.entry(action).or_default().push(key_name(key));
+    }
+    for names in keys.values_mut() {
+        names.sort();
+    }
+    keys.entry(Action::Palette)
+        .or_default()
+        .push(String::from("ctrl-p"));
+    keys
+}
+
+// Name of a key as written in the config file, the reverse of parse_key
+
+#[cfg(feature = "tui")]
+fn key_name(key: KeyCode) -> String {
+    let name = match key {
+        KeyCode::Char(' ') => "space",
+        KeyCode::Char(c) => return c.to_string(),
+        KeyCode::F(number) => return format!("f{number}"),
+        KeyCode::Up => "up",
+        KeyCode::Down => "down",
+        KeyCode::Left => "left",
+        KeyCode::Right => "right",
+        KeyCode::Enter => "enter",
+        KeyCode::Esc => "esc",
+        KeyCode::Tab => "tab",
+        KeyCode::Backspace => "backspace",
+        KeyCode::Delete => "delete",
+        KeyCode::Insert => "insert",
+        KeyCode::Home => "home",
+        KeyCode::End => "end",
+        KeyCode::PageUp => "pageup",
+        KeyCode::PageDown => "pagedown",
+        _ => "?",
+    };
+    name.to_string()
+}
+
+// The name a field is shown under: its configured alias, or the field name itself
+
+pub fn column_label<'a>(aliases: &'a HashMap<String, String>, name: &'a str) -> &'a str {
+    aliases.get(name).map_or(name, String::as_str)
+}
+
+// Count values into equal-width buckets spanning their range, returning each bucket's lower bound and count.
+// When every value is the same there is a single bucket holding all of them.
+
+pub fn histogram(values: &[f64], buckets: usize) -> Vec<(f64, u64)> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = max - min;
+    if span <= 0.0 || !span.is_finite() {
+        return vec![(min, values.len() as u64)];
+    }
+
+    let width = span / buckets as f64;
+    let mut counts = vec![0; buckets];
+    for value in values {
+        // The largest value belongs in the last bucket rather than one past it
+        let bucket = (((value - min) / width) as usize).min(buckets - 1);
+        counts[bucket] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| (min + width * i as f64, count))
+        .collect()
+}
+
+// Find a field in a mapped row. A key naming a column is looked up directly, otherwise the longest column name
+// the key starts with (followed by a dot) is taken and the rest of the key is followed into that column's value,
+// so "host.os.version" is found in a "host" or "host.os" column holding an object
+
+pub fn field_value<'a>(map: &'a JsonMap, key: &str) -> Option<&'a JsonValue> {
+    if let Some(value) = map.get(key) {
+        return Some(value);
+    }
+    let mut prefix = key;
+    while let Some((head, _)) = prefix.rsplit_once('.') {
+        if let Some(value) = map.get(head) {
+            return lookup_path(value, &key[head.len() + 1..]);
+        }
+        prefix = head;
+    }
+    None
+}
+
+// Walk a dotted path into a value, stepping into objects by key and into arrays by index,
+// so "os.version" and "ip.0" both resolve
+
+fn lookup_path<'a>(value: &'a JsonValue, path: &str) -> Option<&'a JsonValue> {
+    path.split('.')
+        .try_fold(value, |value, segment| match value {
+            JsonValue::Object(object) => object.get(segment),
+            JsonValue::Array(values) => values.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+// Text of a value in a column of the given type: types with a dedicated display format use it,
+// and anything else shows strings without their surrounding quotes and other values in compact JSON form
+
+pub fn cell_text(value: &JsonValue, column_type: &str) -> String {
+    TypedCell::new(value, column_type).text(column_type)
+}
+
+// Shorten text to the given width, ending it with an ellipsis when anything was cut off,
+// or with "..." in ASCII mode
+
+pub fn truncate(text: String, width: usize, ascii: bool) -> String {
+    if text.chars().count() <= width {
+        return text;
+    }
+    let ellipsis = if ascii { "..." } else { "…" };
+    let kept = width.saturating_sub(ellipsis.chars().count());
+    text.chars()
+        .take(kept)
+        .chain(ellipsis.chars())
+        .take(width)
+        .collect()
+}
+
+// Plain text of a value: strings without their quotes, anything else as compact JSON
+
+fn value_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+// The format_typed function renders a value according to its column type when that type has a
+// human-friendly form, as TypedCell::formatted does.
+// It returns None when the type has no special format or the value does not fit it.
+
+pub fn format_typed(value: &JsonValue, column_type: &str) -> Option<String> {
+    TypedCell::new(value, column_type).formatted(column_type)
+}
+
+// Readable form of a UTC timestamp
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string()
+}
+
+// Parse a timestamp leniently: RFC 3339, the same without an offset taken as UTC, or epoch seconds or milliseconds
+// given as a number or as text
+
+fn parse_timestamp(value: &JsonValue) -> Option<DateTime<Utc>> {
+    match value {
+        JsonValue::String(text) => parse_timestamp_text(text),
+        JsonValue::Number(number) => parse_epoch(number.as_f64()?),
+        _ => None,
+    }
+}
+
+// Parse RFC 3339 text, the same without an offset taken as UTC, or an epoch number written as text
+
+fn parse_timestamp_text(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|timestamp| timestamp.to_utc())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").map(|naive| naive.and_utc())
+        })
+        .ok()
+        .or_else(|| parse_epoch(text.trim().parse().ok()?))
+}
+
+// The time an epoch number stands for, telling seconds from milliseconds by its size
+
+fn parse_epoch(number: f64) -> Option<DateTime<Utc>> {
+    let millis = if number.abs() < EPOCH_SECONDS_LIMIT {
+        number * 1000.0
+    } else {
+        number
+    };
+    DateTime::from_timestamp_millis(millis.round() as i64)
+}
+
+// The @timestamp of a document, taken from its first row
+
+pub fn log_timestamp(log: &Log) -> Option<DateTime<Utc>> {
+    map_row(log, 0).get(TIMESTAMP).and_then(parse_timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_values_leave_mapped_document_empty() {
+        let log: Log = serde_json::from_str(
+            r#"{"values":[],"took":0,"columns":[{"name":"host.name","type":"keyword"}]}"#,
+        )
+        .unwrap();
+        assert!(map_document(&log).is_empty());
+
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        state.update_log(DEFAULT_CHANNEL, log);
+        assert!(state.mapped_document.is_empty());
+        assert_eq!(state.selected_row, None);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn configured_keys_replace_the_defaults_of_their_action() {
+        let config: Config =
+            serde_json::from_str(r#"{"keybindings":{"quit":"x","down":["t","pagedown","??"]}}"#)
+                .unwrap();
+        let (bindings, warnings) = key_bindings(&config.keybindings);
+
+        assert_eq!(bindings.get(&KeyCode::Char('x')), Some(&Action::Quit));
+        assert_eq!(bindings.get(&KeyCode::Char('q')), None);
+        assert_eq!(bindings.get(&KeyCode::PageDown), Some(&Action::Down));
+        assert_eq!(bindings.get(&KeyCode::Char('j')), None);
+        assert_eq!(bindings.get(&KeyCode::Char('k')), Some(&Action::Up));
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn help_footer_shows_the_shortest_configured_keys() {
+        let config: Config =
+            serde_json::from_str(r#"{"keybindings":{"quit":"x","down":["pagedown","t"]}}"#)
+                .unwrap();
+        let (bindings, _) = key_bindings(&config.keybindings);
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        state.action_keys = action_keys(&bindings);
+
+        assert_eq!(state.hint_keys(&[Action::Quit]).as_deref(), Some("x"));
+        assert_eq!(
+            state.hint_keys(&[Action::Down, Action::Up]).as_deref(),
+            Some("t/k")
+        );
+        assert_eq!(state.hint_keys(&[Action::Palette]).as_deref(), Some(":"));
+    }
+
+    #[test]
+    fn dotted_fields_reach_into_nested_values() {
+        let log: Log = serde_json::from_str(
+            r#"{"values":[[{"os":{"version":"12"}},["10.0.0.1","10.0.0.2"]]],
+                "columns":[{"name":"host","type":"object"},{"name":"host.ip","type":"ip"}]}"#,
+        )
+        .unwrap();
+        let row = map_row(&log, 0);
+
+        assert_eq!(
+            field_value(&row, "host.os.version"),
+            Some(&JsonValue::from("12"))
+        );
+        assert_eq!(
+            field_value(&row, "host.ip.1"),
+            Some(&JsonValue::from("10.0.0.2"))
+        );
+        assert_eq!(field_value(&row, "host.os.name"), None);
+        assert_eq!(field_value(&row, "host.ip.9"), None);
+    }
+
+    #[test]
+    fn numeric_columns_sort_by_typed_value() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        let log: Log = serde_json::from_str(
+            r#"{"values":[[10],["9"],[9007199254740993],[null],[9007199254740992]],
+                "columns":[{"name":"bytes","type":"long"}]}"#,
+        )
+        .unwrap();
+        state.update_log(DEFAULT_CHANNEL, log);
+        state.sort = Some((0, SortDirection::Ascending));
+
+        // Strings in a numeric column read as numbers, and large integers keep their precision
+        assert_eq!(state.visible_rows(), vec![1, 0, 4, 2, 3]);
+        assert_eq!(state.live_document.cells[1][0], TypedCell::Int(9));
+        assert_eq!(
+            state.live_document.cells[2][0].text("long"),
+            "9007199254740993"
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_rejected_before_it_is_read() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, 1024, None);
+
+        // Only the header claims a large body, so a 413 means nothing was buffered.
+        // It is set after the body because warp::test fills in the real length
+        let response = warp::test::request()
+            .method("POST")
+            .path("/data")
+            .body("{}")
+            .header("content-length", "1048576")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(state.read().unwrap().counters.documents(), 0);
+    }
+
+    #[tokio::test]
+    async fn chunked_bodies_are_measured_as_they_arrive() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, 1024, None);
+        let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        // Send the body in two chunks with no Content-Length, as a streaming client would
+        let post = |body: String| async move {
+            let (first, second) = body.split_at(body.len() / 2);
+            let request = format!(
+                "POST /data HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n\
+                 {:x}\r\n{first}\r\n{:x}\r\n{second}\r\n0\r\n\r\n",
+                first.len(),
+                second.len(),
+            );
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = post(String::from(r#"{"values":[["web-1"]]}"#)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert_eq!(state.read().unwrap().counters.documents(), 1);
+
+        let response = post(format!(r#"{{"values":[["{}"]]}}"#, "x".repeat(2048))).await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+        assert_eq!(state.read().unwrap().counters.documents(), 1);
+    }
+
+    #[tokio::test]
+    async fn posts_over_the_rate_limit_get_429() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, Some(1));
+        let post = || {
+            warp::test::request()
+                .method("POST")
+                .path("/data")
+                .body(r#"{"values":[]}"#)
+                .reply(&routes)
+        };
+
+        assert_eq!(post().await.status(), StatusCode::OK);
+        let response = post().await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "1");
+        assert_eq!(state.read().unwrap().counters.documents(), 1);
+    }
+
+    #[tokio::test]
+    async fn arrays_pay_the_rate_limit_per_document() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, Some(2));
+        let post = |path: &str, body: &str| {
+            warp::test::request()
+                .method("POST")
+                .path(path)
+                .body(body)
+                .reply(&routes)
+        };
+
+        let response = post("/data?format=mapped", r#"[{"values":[]},{"values":[]}]"#).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.read().unwrap().counters.documents(), 0);
+
+        let array = r#"[{"values":[]},{"values":[]},{"values":[]}]"#;
+        assert_eq!(post("/data", array).await.status(), StatusCode::OK);
+        let response = post("/data", r#"{"values":[]}"#).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(state.read().unwrap().counters.documents(), 3);
+    }
+
+    #[tokio::test]
+    async fn bulk_lines_split_across_chunks_are_joined() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let chunks = [
+            "{\"values\":[[1]]}\n{\"val",
+            "ues\":[[2],[2]]}\nnot json\n",
+            "\n{\"values\":[[3]]}",
+        ];
+        let body = futures_util::stream::iter(
+            chunks.map(|chunk| Ok::<_, warp::Error>(warp::hyper::body::Bytes::from(chunk))),
+        );
+
+        let summary = ingest_ndjson(state.clone(), body, MAX_BODY_BYTES).await;
+        assert_eq!(
+            (summary.ingested, summary.documents, summary.errors),
+            (4, 3, 1)
+        );
+        assert!(!summary.truncated);
+        let state = state.read().unwrap();
+        assert_eq!(state.counters.documents(), 3);
+        assert_eq!(state.current_document.values, [[serde_json::json!(3)]]);
+    }
+
+    #[tokio::test]
+    async fn bulk_body_that_breaks_off_is_reported_truncated() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let chunks = [
+            Ok(warp::hyper::body::Bytes::from("{\"values\":[[1]]}\n{\"val")),
+            Err(io::Error::other("connection reset")),
+        ];
+        let body = futures_util::stream::iter(chunks);
+
+        let summary = ingest_ndjson(state.clone(), body, MAX_BODY_BYTES).await;
+        assert_eq!(
+            (summary.ingested, summary.documents, summary.errors),
+            (1, 1, 0)
+        );
+        assert!(summary.truncated);
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({"ingested": 1, "documents": 1, "errors": 0, "truncated": true})
+        );
+        assert_eq!(state.read().unwrap().counters.documents(), 1);
+    }
+
+    #[test]
+    fn numbers_are_grouped_and_rounded_for_display() {
+        let numbers = NumberFormat {
+            thousands: true,
+            precision: Some(2),
+        };
+        assert_eq!(numbers.integer(1234567), "1,234,567");
+        assert_eq!(numbers.integer(-123), "-123");
+        assert_eq!(numbers.float(-1234.567), "-1,234.57");
+        assert_eq!(NumberFormat::default().float(1234.5), "1234.5");
+
+        // Only cells of numeric columns are formatted
+        let cell = TypedCell::new(&serde_json::json!(1234567), "long");
+        assert_eq!(cell.display("long", &numbers), "1,234,567");
+        assert_eq!(cell.display("keyword", &numbers), "1234567");
+    }
+
+    #[test]
+    fn timestamps_are_normalized_to_rfc3339() {
+        let mut log: Log = serde_json::from_str(
+            r#"{"columns":[{"name":"@timestamp","type":"long"}],"values":[
+                [1700000000123],
+                [1700000000],
+                ["2023-11-14T22:13:20Z"],
+                ["2023-11-14T23:13:20.5+01:00"],
+                ["2023-11-14T22:13:20.123456"],
+                [null]
+            ]}"#,
+        )
+        .unwrap();
+        log.normalize_timestamps();
+        log.type_cells();
+
+        let timestamps: Vec<&JsonValue> = (0..5).filter_map(|row| log.value(row, 0)).collect();
+        assert_eq!(
+            timestamps,
+            [
+                "2023-11-14T22:13:20.123Z",
+                "2023-11-14T22:13:20.000Z",
+                "2023-11-14T22:13:20.000Z",
+                "2023-11-14T22:13:20.500Z",
+                "2023-11-14T22:13:20.123Z",
+            ]
+        );
+        assert_eq!(log.value(5, 0), Some(&JsonValue::Null));
+        assert_eq!(log.column_type(0), "date");
+        assert_eq!(
+            log.cell(1, 0),
+            Some(&TypedCell::Str(String::from("2023-11-14T22:13:20.000Z")))
+        );
+        assert_eq!(
+            mapped_rows(&log)[0]["@timestamp"],
+            "2023-11-14T22:13:20.123Z"
+        );
+
+        // The document itself keeps every value and its column type as they arrived
+        assert_eq!(log.values[1][0], serde_json::json!(1700000000));
+        assert_eq!(log.columns[0].column_type, "long");
+        let json = serde_json::to_value(&log).unwrap();
+        assert_eq!(json["values"][3][0], "2023-11-14T23:13:20.5+01:00");
+        assert!(json.get("original_timestamps").is_none());
+        let originals = log.original_timestamps();
+        assert_eq!(originals.len(), 5);
+        assert_eq!(originals[&1], &serde_json::json!(1700000000));
+    }
+
+    #[test]
+    fn unparseable_timestamps_are_left_and_flagged() {
+        let mut log: Log = serde_json::from_str(
+            r#"{"columns":[{"name":"@timestamp","type":"keyword"}],"values":[["yesterday"]]}"#,
+        )
+        .unwrap();
+        log.normalize_timestamps();
+
+        assert_eq!(log.values[0][0], "yesterday");
+        assert!(log.original_timestamps().is_empty());
+        assert_eq!(
+            type_warnings(&log, &HashSet::new(), false),
+            ["row 1, @timestamp: \"yesterday\" is not a valid timestamp"]
+        );
+    }
+
+    #[tokio::test]
+    async fn search_hits_are_flattened_into_rows() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+        let post = |body: &'static str| {
+            warp::test::request()
+                .method("POST")
+                .path("/data/search")
+                .body(body)
+                .reply(&routes)
+        };
+
+        let response = post(
+            r#"{"took":3,"hits":{"hits":[
+                {"_source":{"host":{"name":"web-1"},"bytes":512}},
+                {"_source":{"host":{"name":"web-2","ip":["10.0.0.2"]}}}
+            ]}}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let document = state.read().unwrap().current_document.clone();
+        let columns: Vec<(&str, &str)> = document
+            .columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.column_type.as_str()))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("bytes", "long"),
+                ("host.name", "keyword"),
+                ("host.ip", "keyword")
+            ]
+        );
+        assert_eq!(
+            document.values,
+            [
+                serde_json::json!([512, "web-1", null]),
+                serde_json::json!([null, "web-2", ["10.0.0.2"]]),
+            ]
+            .map(|row| row.as_array().unwrap().clone())
+        );
+
+        // A body without hits is refused and leaves the dashboard alone
+        let response = post(r#"{"took":3}"#).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.read().unwrap().counters.documents(), 1);
+    }
+
+    #[tokio::test]
+    async fn history_is_paged_newest_first() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+        for took in 1..=5 {
+            warp::test::request()
+                .method("POST")
+                .path("/data")
+                .body(format!(r#"{{"took":{took},"values":[]}}"#))
+                .reply(&routes)
+                .await;
+        }
+
+        let response = warp::test::request()
+            .path("/history?limit=2&offset=1")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-total-count"], "5");
+        let page: Vec<Log> = serde_json::from_slice(response.body()).unwrap();
+        let took: Vec<u32> = page.iter().map(|log| log.took).collect();
+        assert_eq!(took, [4, 3]);
+
+        let response = warp::test::request()
+            .path("/history/nope")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn repeated_documents_are_left_out_of_history_with_dedup() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        state.dedup = true;
+        let log = |took: u32, id: &str| -> Log {
+            serde_json::from_str(&format!(
+                r#"{{"took":{took},"columns":[{{"name":"id","type":"keyword"}}],"values":[["{id}"]]}}"#
+            ))
+            .unwrap()
+        };
+        state.update_log(DEFAULT_CHANNEL, log(1, "a"));
+        state.update_log(DEFAULT_CHANNEL, log(2, "a"));
+        state.update_log(DEFAULT_CHANNEL, log(3, "b"));
+        state.update_log(DEFAULT_CHANNEL, log(4, "a"));
+        assert_eq!(state.history_len(), 3);
+        assert_eq!(state.counters.duplicates(), 1);
+        assert_eq!(state.counters.documents(), 4);
+    }
+
+    // Post a document encoded in a binary format, asking for the same format back when accept is set,
+    // and return the reply's content type and body
+    async fn post_binary(
+        state: &SharedAppState,
+        content_type: &str,
+        body: Vec<u8>,
+        accept: bool,
+    ) -> (String, Vec<u8>) {
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+        let mut request = warp::test::request()
+            .method("POST")
+            .path("/data")
+            .header("content-type", content_type)
+            .body(body);
+        if accept {
+            request = request.header("accept", content_type);
+        }
+        let response = request.reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let reply_type = response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        (reply_type, response.body().to_vec())
+    }
+
+    #[tokio::test]
+    async fn cbor_and_msgpack_documents_round_trip() {
+        let document = serde_json::json!({
+            "values": [["web-1", 200, true], ["web-2", null, false]],
+            "took": 7,
+            "columns": [
+                {"name": "host.name", "type": "keyword"},
+                {"name": "status", "type": "long"},
+                {"name": "ok", "type": "boolean"}
+            ]
+        });
+        let mut cbor = vec![];
+        ciborium::into_writer(&document, &mut cbor).unwrap();
+        let msgpack = rmp_serde::to_vec_named(&document).unwrap();
+
+        let state = AppState::new(HISTORY_CAPACITY);
+        let (reply_type, body) = post_binary(&state, "application/cbor", cbor.clone(), true).await;
+        assert_eq!(reply_type, "application/cbor");
+        let reply: JsonValue = ciborium::from_reader(body.as_slice()).unwrap();
+        assert_eq!(reply, document);
+        assert_eq!(
+            state.read().unwrap().mapped_document[1]["host.name"],
+            "web-2"
+        );
+
+        let (reply_type, body) = post_binary(&state, "application/msgpack", msgpack, true).await;
+        assert_eq!(reply_type, "application/msgpack");
+        let reply: JsonValue = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(reply, document);
+        assert_eq!(state.read().unwrap().history_len(), 2);
+
+        // Without a matching Accept header the reply stays JSON
+        let (reply_type, body) = post_binary(&state, "application/cbor", cbor, false).await;
+        assert_eq!(reply_type, "application/json");
+        let reply: JsonValue = serde_json::from_slice(&body).unwrap();
+        assert_eq!(reply, document);
+    }
+
+    #[tokio::test]
+    async fn info_reports_settings_without_secrets() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        {
+            let mut state = state.write().unwrap();
+            state.info.address = String::from("127.0.0.1:33433");
+            state.info.fields = vec![String::from("host.name")];
+        }
+        let routes = routes(state, Some(String::from("secret")), MAX_BODY_BYTES, None);
+        let response = warp::test::request().path("/info").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let info: JsonValue = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            info,
+            serde_json::json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "address": "127.0.0.1:33433",
+                "fields": ["host.name"],
+                "history_capacity": HISTORY_CAPACITY,
+                "features": {"tls": false, "auth": true, "persistence": false}
+            })
+        );
+    }
+
+    #[test]
+    fn scrolled_feed_keeps_its_place_until_it_follows_again() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        let log = || serde_json::from_str::<Log>(r#"{"values":[]}"#).unwrap();
+        for _ in 0..3 {
+            state.update_log(DEFAULT_CHANNEL, log());
+        }
+        assert!(state.follow);
+
+        state.scroll_feed(1);
+        state.update_log(DEFAULT_CHANNEL, log());
+        assert!(!state.follow);
+        assert_eq!(state.feed_scroll, 2);
+
+        state.scroll_feed(isize::MAX);
+        assert_eq!(state.feed_scroll, 3);
+
+        // Scrolling down only follows again once it reaches the newest document
+        state.scroll_feed(-2);
+        assert!(!state.follow);
+        state.scroll_feed(-1);
+        assert!(state.follow);
+        state.scroll_feed(-1);
+        assert!(state.follow);
+        assert_eq!(state.feed_scroll, 0);
+        state.scroll_feed(1);
+
+        state.follow_feed();
+        state.update_log(DEFAULT_CHANNEL, log());
+        assert!(state.follow);
+        assert_eq!(state.feed_scroll, 0);
+    }
+
+    #[test]
+    fn time_range_limits_history_to_recent_timestamps() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        let log = |host: &str, timestamp: JsonValue| -> Log {
+            serde_json::from_value(serde_json::json!({
+                "columns": [
+                    {"name": "@timestamp", "type": "date"},
+                    {"name": "host.name", "type": "keyword"}
+                ],
+                "values": [[timestamp, host]]
+            }))
+            .unwrap()
+        };
+        let ago = |minutes| (Utc::now() - chrono::Duration::minutes(minutes)).to_rfc3339();
+        state.update_log(DEFAULT_CHANNEL, log("untimed", JsonValue::Null));
+        state.update_log(DEFAULT_CHANNEL, log("old", ago(10).into()));
+        state.update_log(DEFAULT_CHANNEL, log("new", ago(0).into()));
+        assert_eq!(state.history_sequence(), [0, 1, 2]);
+
+        state.cycle_time_range();
+        assert_eq!(state.time_range, TimeRange::LastMinute);
+        assert_eq!(state.history_sequence(), [0]);
+        state.search.query = String::from("host.name:e");
+        state.run_search();
+        assert_eq!(state.search.hits.len(), 1);
+
+        state.cycle_time_range();
+        state.cycle_time_range();
+        assert_eq!(state.time_range, TimeRange::LastFifteenMinutes);
+        assert_eq!(state.history_sequence(), [0, 1]);
+
+        // Stepping back to an old entry and narrowing the range past it returns to live data
+        state.history_older();
+        assert_eq!(state.history_cursor, Some(1));
+        for _ in 0..3 {
+            state.cycle_time_range();
+        }
+        assert_eq!(state.time_range, TimeRange::LastMinute);
+        assert_eq!(state.history_cursor, None);
+    }
+
+    #[test]
+    fn truncated_text_fits_its_width_in_either_mode() {
+        let text = String::from("abcdefgh");
+        assert_eq!(truncate(text.clone(), 8, true), "abcdefgh");
+        assert_eq!(truncate(text.clone(), 5, false), "abcd…");
+        assert_eq!(truncate(text.clone(), 5, true), "ab...");
+        assert_eq!(truncate(text, 2, true), "..");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn symbols_are_spelt_in_ascii_when_asked() {
+        assert_eq!(Symbol::Check.render(true, false).0, "✓");
+        assert_eq!(Symbol::Check.render(true, true).0, "y");
+        assert_eq!(Symbol::Check.render(false, true).0, "n");
+        assert_eq!(
+            Symbol::Dot.render(false, true),
+            ("*", Style::new().fg(Color::Red))
+        );
+    }
+
+    #[test]
+    fn expired_documents_leave_history_except_the_one_viewed() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        state.history_ttl = Some(Duration::from_secs(60));
+        for took in 1..=4 {
+            let log = serde_json::from_str(&format!(r#"{{"took":{took},"values":[]}}"#)).unwrap();
+            state.update_log(DEFAULT_CHANNEL, log);
+        }
+        let old = Instant::now() - Duration::from_secs(120);
+        for log in state.history.iter_mut().take(3) {
+            log.received = Some(old);
+        }
+
+        // The third newest is on screen, so only the oldest can go, and the screen says it is expired
+        state.history_cursor = Some(2);
+        state.expire_history();
+        assert_eq!(state.history_len(), 3);
+        assert_eq!(state.displayed_document().took, 2);
+        assert!(state.is_expired());
+
+        // Back on the live document everything past the TTL is evicted
+        state.history_cursor = None;
+        state.expire_history();
+        assert_eq!(state.history_len(), 1);
+        assert_eq!(state.displayed_document().took, 4);
+        assert!(!state.is_expired());
+    }
+
+    #[tokio::test]
+    async fn expired_documents_are_swept_without_a_dashboard() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        state.write().unwrap().history_ttl = Some(Duration::from_secs(60));
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+        for took in 1..=3 {
+            warp::test::request()
+                .method("POST")
+                .path("/data")
+                .body(format!(r#"{{"took":{took},"values":[]}}"#))
+                .reply(&routes)
+                .await;
+        }
+        {
+            let mut state = state.write().unwrap();
+            let old = Instant::now() - Duration::from_secs(120);
+            for log in state.history.iter_mut().take(2) {
+                log.received = Some(old);
+            }
+        }
+
+        // Nothing more is posted and nothing draws, so only the sweep can evict them
+        let sweep = tokio::spawn(expire_thread(state.clone()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        sweep.abort();
+        let response = warp::test::request().path("/history").reply(&routes).await;
+        assert_eq!(response.headers()["x-total-count"], "1");
+        assert_eq!(state.read().unwrap().history_len(), 1);
+    }
+
+    #[tokio::test]
+    async fn arrays_of_documents_are_ingested_in_order() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+        let response = warp::test::request()
+            .method("POST")
+            .path("/data")
+            .body(r#"[{"took":1,"values":[["a"]]},{"took":2,"values":[["b"],["c"]]}]"#)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.body(),
+            r#"{"ingested":3,"documents":2,"errors":0}"#
+        );
+        assert_eq!(response.headers()[REQUEST_ID_HEADER].len(), 36);
+        {
+            let state = state.read().unwrap();
+            assert_eq!(state.current_document.took, 2);
+            assert_eq!(state.history_len(), 2);
+        }
+
+        // A bad element is reported with serde's own error, whose column 38 falls inside the second element,
+        // and nothing is ingested
+        let response = warp::test::request()
+            .method("POST")
+            .path("/data")
+            .body(r#"[{"took":3,"values":[]},{"took":"slow","values":[]}]"#)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error: JsonValue = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            error["detail"],
+            r#"invalid type: string "slow", expected u32 at line 1 column 38"#
+        );
+        assert_eq!(
+            error["request_id"],
+            response.headers()[REQUEST_ID_HEADER].to_str().unwrap()
+        );
+        assert_eq!(state.read().unwrap().history_len(), 2);
+    }
+
+    #[tokio::test]
+    async fn redacted_fields_are_masked_in_responses_but_kept_in_history() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        state.write().unwrap().redact = HashSet::from([String::from("password")]);
+        let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+        let body = r#"{"took":1,"columns":[{"name":"user","type":"keyword"},{"name":"password","type":"keyword"}],"values":[["ann","hunter2"]]}"#;
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/data")
+            .body(body)
+            .reply(&routes)
+            .await;
+        let reply: Log = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            reply.values,
+            [[serde_json::json!("ann"), serde_json::json!(REDACTED)]]
+        );
+        assert_eq!(
+            state.read().unwrap().history[0].values[0][1],
+            serde_json::json!("hunter2")
+        );
+
+        // Dropping keeps the value out of history as well
+        state.write().unwrap().redact_mode = RedactMode::Drop;
+        warp::test::request()
+            .method("POST")
+            .path("/data")
+            .body(body)
+            .reply(&routes)
+            .await;
+        let state = state.read().unwrap();
+        assert_eq!(state.current_document.values[0][1], JsonValue::Null);
+        assert!(state.is_redacted("password", 0));
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn saved_theme_reads_back_exactly() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        // Unset colours the default preset would fill in have to come back unset
+        let theme = Theme {
+            alert: None,
+            number: Some(Color::Rgb(1, 2, 3)),
+            ..Theme::default()
+        };
+        state.theme = theme;
+        let saved = serde_json::to_string(&UiState::capture(&state)).unwrap();
+
+        state.theme = Theme::default();
+        let restored: UiState = serde_json::from_str(&saved).unwrap();
+        restored.restore(&mut state);
+        assert_eq!(state.theme, theme);
+    }
+
+    #[test]
+    fn redacted_values_are_masked_in_type_warnings() {
+        let state = AppState::new(HISTORY_CAPACITY);
+        let mut state = state.write().unwrap();
+        state.redact = HashSet::from([String::from("pin")]);
+        let log: Log = serde_json::from_str(
+            r#"{"columns":[{"name":"pin","type":"long"},{"name":"port","type":"long"}],"values":[["s3cret","http"]]}"#,
+        )
+        .unwrap();
+        state.update_log(DEFAULT_CHANNEL, log);
+
+        assert_eq!(
+            state.type_warnings,
+            [
+                format!("row 1, pin: {REDACTED} is not a valid long"),
+                String::from("row 1, port: \"http\" is not a valid long"),
+            ]
+        );
+    }
+}
@@ -1,229 +1,2507 @@
+use arboard::Clipboard;
+use chrono::Utc;
+use clap::Parser;
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode, KeyEventKind},
-    widgets::Paragraph,
-    DefaultTerminal,
+    crossterm::{
+        event::{
+            self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind,
+            KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+        },
+        execute,
+    },
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    symbols::{bar, border},
+    text::{Line, Span, Text},
+    widgets::{
+        Bar, BarChart, BarGroup, Block, Cell, List, ListItem, ListState, Paragraph, Row, Table,
+        TableState, Tabs, Wrap,
+    },
+    DefaultTerminal, Frame,
 };
-use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    io,
+    cmp,
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufWriter, Write},
     net::{Ipv4Addr, SocketAddrV4},
-    sync::{Arc, Mutex},
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex, PoisonError,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use warp::Filter;
+use regex::Regex;
+use reqwest::Url;
+use tokio::{io::AsyncBufReadExt, net::UnixListener, task::JoinHandle};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+
+use rs_es_dashview::*;
 
-const ADDRESS: [u8; 4] = [127, 0, 0, 1];
-const PORT: u16 = 33433;
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 10;
 
-const TIMESTAMP: &str = "@timestamp";
-const AGENT_ID: &str = "agent.id";
-const HOST_NAME: &str = "host.name";
-const HOST_OS_NAME: &str = "host.os.name";
-const USER_NAME: &str = "user.name";
-const HOST_IP: &str = "host.ip";
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const ALERT_FLASH_INTERVAL: Duration = Duration::from_millis(500);
+
+// What borders and histogram bars are drawn with in ASCII mode
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+const ASCII_BARS: bar::Set = bar::Set {
+    full: "#",
+    seven_eighths: "#",
+    three_quarters: "#",
+    five_eighths: "#",
+    half: "=",
+    three_eighths: "=",
+    one_quarter: "-",
+    one_eighth: "-",
+    empty: " ",
+};
 
-type JsonValue = serde_json::Value;
-type JsonMap = HashMap<String, JsonValue>;
-type SharedAppState = Arc<Mutex<AppState>>;
 type TerminalBackend = ratatui::Terminal<ratatui::prelude::CrosstermBackend<io::Stdout>>;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Log {
-    values: Vec<Vec<JsonValue>>, // A 2D vector holding the log values
-    took: u32,                   // Time taken to process the log
-    columns: Vec<Column>,        // Metadata about the columns in the log
+#[derive(Parser, Debug)]
+#[command(version, about = "Terminal viewer for Logstash HTTP poller output")]
+struct Args {
+    #[arg(long, default_value_t = Ipv4Addr::from(ADDRESS))]
+    bind: Ipv4Addr, // Address the ingest server listens on
+    #[arg(long, default_value_t = PORT)]
+    port: u16, // Port the ingest server listens on
+    #[arg(long, default_value_t = HISTORY_CAPACITY, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    history_capacity: usize, // Number of received documents kept in history
+    #[arg(long, value_name = "SECS", value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    history_ttl: Option<u64>, // Seconds a document is kept in history, as well as the count cap
+    #[arg(long, default_value = CONFIG_PATH)]
+    config: PathBuf, // JSON config file, defaults are used when it does not exist
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = STATE_PATH, conflicts_with_all = ["once", "headless"])]
+    persist_state: Option<PathBuf>, // File the filter, sort, hidden columns, channel and theme are saved to on exit and restored from
+
+    #[arg(long, default_value_t = REFRESH_MS)]
+    refresh_ms: u64, // Longest wait between redraws when nothing changes
+    #[arg(long, default_value_t = DEBOUNCE_MS)]
+    debounce_ms: u64, // Shortest time between changes of the live document on screen, 0 to show every one
+    #[arg(long, default_value_t = STALE_SECS)]
+    stale_secs: u64, // Seconds without a document after which the status bar marks the data stale
+    #[arg(long)]
+    log_file: Option<PathBuf>, // Daily-rotated log file, logging is off when unset
+    #[arg(long, default_value_t = LevelFilter::INFO)]
+    log_level: LevelFilter, // Most verbose level written to the log file
+    #[arg(long, conflicts_with_all = ["bind", "port"])]
+    unix_socket: Option<PathBuf>, // Unix socket the ingest server listens on instead of TCP
+    #[arg(long, requires = "tls_key", conflicts_with = "unix_socket")]
+    tls_cert: Option<PathBuf>, // PEM certificate chain, serving HTTPS together with --tls-key
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>, // PEM private key for --tls-cert
+    #[arg(long, default_value_t = MAX_BODY_BYTES)]
+    max_body_bytes: u64, // Largest request body /data accepts after decompression for gzip, and longest /bulk line
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit: Option<u32>, // Documents per second POST /data accepts before answering 429, unlimited when unset
+    #[arg(long)]
+    auth_token: Option<String>, // Bearer token required by the write routes, which are open when unset
+    #[arg(long)]
+    output: Option<PathBuf>, // NDJSON file every received document is appended to
+    #[arg(long, default_value = ".")]
+    export_dir: PathBuf, // Directory CSV exports are written to
+    #[arg(long)]
+    replay: Option<PathBuf>, // NDJSON file to play back instead of starting the server
+    #[arg(long = "loop", requires = "replay")]
+    looping: bool, // Start the replay over when it reaches the end of the file
+    #[arg(long, conflicts_with_all = ["replay", "unix_socket", "tls_cert"])]
+    stdin: bool, // Read documents piped to stdin instead of starting the server
+    #[arg(long)]
+    dedup: bool, // Leave a document out of history when it repeats the latest one on its channel
+    #[arg(long, value_name = "FIELD", requires = "dedup")]
+    dedup_key: Option<String>, // Column compared by --dedup instead of every value
+    #[arg(long)]
+    ascii: bool, // Stick to ASCII for terminals without Unicode, spelling symbols with ASCII characters
+    #[arg(long, value_parser = parse_es_url, conflicts_with = "replay")]
+    es_url: Option<Url>, // SQL endpoint of the cluster to poll, built from its base URL
+    #[arg(long, requires = "es_url")]
+    es_query: Option<String>, // ES SQL query polled into the default channel
+    #[arg(long, value_name = "SECONDS", default_value_t = ES_INTERVAL_SECS, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    es_interval: u64, // Time between polls, for --es-query and config polls without their own interval
+    #[arg(long, requires = "es_url")]
+    es_user: Option<String>, // User name for basic auth against the cluster
+    #[arg(long, requires = "es_user")]
+    es_pass: Option<String>, // Password for basic auth against the cluster
+    #[arg(long)]
+    once: bool, // Print the first received document as JSON and exit instead of starting the TUI
+    #[arg(long, value_name = "SECONDS", requires = "once")]
+    timeout: Option<u64>, // Longest --once waits for a document before exiting with an error
+    #[arg(long, visible_alias = "no-tui", conflicts_with_all = ["once", "replay", "stdin"])]
+    headless: bool, // Run only the server, without the terminal UI, until interrupted
 }
 
-impl Log {
-    fn new() -> Self {
-        Self {
-            values: vec![vec![]],
-            took: 0,
-            columns: vec![],
-        }
+impl Args {
+    fn address(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.bind, self.port)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Column {
-    name: String, // Name of the column
-    #[serde(rename = "type")]
-    column_type: String, // Type of the column, renamed to "type" in JSON
+// Turn the cluster URL given with --es-url into its SQL endpoint so a bad URL is reported by clap
+
+fn parse_es_url(text: &str) -> Result<Url, String> {
+    Url::parse(text)
+        .and_then(|base| base.join("_sql?format=json"))
+        .map_err(|e| e.to_string())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct AppState {
-    current_document: Log,    // The current log document
-    mapped_document: JsonMap, // A map of column names to their values
+// Actions the footer hints at while the table has the keys, each group under one label
+const TABLE_HINTS: [(&[Action], &str); 7] = [
+    (&[Action::Quit], "quit"),
+    (&[Action::Down, Action::Up], "move"),
+    (&[Action::Inspector], "inspect"),
+    (&[Action::Filter], "filter"),
+    (&[Action::Search], "search"),
+    (&[Action::Palette], "all keys"),
+    (&[Action::Help], "hide help"),
+];
+
+// Actions the footer hints at while the inspector is open
+const INSPECTOR_HINTS: [(&[Action], &str); 6] = [
+    (&[Action::CloseInspector], "close"),
+    (&[Action::InspectorDown, Action::InspectorUp], "scroll"),
+    (&[Action::Down, Action::Up], "row"),
+    (&[Action::Wrap], "wrap"),
+    (&[Action::Palette], "all keys"),
+    (&[Action::Help], "hide help"),
+];
+
+// How the rows table was last drawn. The draw thread keeps it between frames, outside the application state
+// so rendering only needs to read that, and the input thread looks at it to translate clicks and page the inspector.
+#[derive(Debug, Default)]
+struct View {
+    table: TableState, // Scroll and selection state of the rows table
+    area: Rect,        // Where the table was last drawn
+    col_offset: usize, // Index of the first column shown when the table is scrolled horizontally
 }
 
-impl AppState {
-    fn new() -> SharedAppState {
-        Arc::new(Mutex::new(Self {
-            current_document: Log::new(),
-            mapped_document: HashMap::new(),
-        }))
+type SharedView = Arc<Mutex<View>>;
+
+impl View {
+    // Number of lines PageUp / PageDown scroll the inspector by: the height inside its border.
+    // Panels share the table area evenly with the table, so the table's last height is the inspector's too.
+    fn inspector_page(&self) -> u16 {
+        self.area.height.saturating_sub(2).max(1)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // Parse the command line before touching the terminal so errors print normally
+    let args = Args::parse();
+    let config = match Config::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!(
+                "error: failed to load config {}: {e}",
+                args.config.display()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    // Send logs to a file, since stdout belongs to the terminal UI
+    let log_guard = match init_logging(&args) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("error: failed to open log file: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // Polling needs a query to send, and the cluster to send configured queries to
+    if args.es_url.is_some() && args.es_query.is_none() && config.polls.is_empty() {
+        eprintln!("error: --es-url needs --es-query or polls in the config");
+        std::process::exit(1);
+    }
+    if let Some(poll) = config.polls.iter().find(|poll| poll.interval == Some(0)) {
+        eprintln!(
+            "error: poll {} needs an interval of at least 1 second",
+            poll.name
+        );
+        std::process::exit(1);
+    }
+    if args.es_url.is_none() && !config.polls.is_empty() {
+        eprintln!("warning: polls in the config are ignored without --es-url");
+        tracing::warn!("polls in the config are ignored without --es-url");
+    }
+
+    // Report key bindings that cannot be used, carrying on with the rest
+    let (bindings, warnings) = key_bindings(&config.keybindings);
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+        tracing::warn!("{warning}");
     }
 
-    // Update the current log and map the document
-    fn update_log(&mut self, new_log: Log) {
-        self.current_document = new_log;
-        self.mapped_document = HashMap::new();
+    // Make sure a replay file can be read before taking over the terminal
+    if let Some(path) = &args.replay {
+        if let Err(e) = File::open(path) {
+            eprintln!("error: failed to open replay {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
 
-        // Map the columns to their respective values
-        for (i, column) in self.current_document.columns.iter().enumerate() {
-            if let Some(value) = self.current_document.values[0].get(i) {
-                self.mapped_document
-                    .insert(column.name.clone(), value.clone());
+    // Bind a Unix socket up front so a path that is taken is reported before the TUI starts
+    let listen = match (&args.unix_socket, &args.replay) {
+        (Some(path), None) => match UnixListener::bind(path) {
+            Ok(listener) => Listen::Unix(listener),
+            Err(e) => {
+                eprintln!("error: failed to bind {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        },
+        _ => match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => Listen::Tls(args.address(), cert.clone(), key.clone()),
+            _ => Listen::Tcp(args.address()),
+        },
+    };
+
+    // Check the certificate and key can be read, since the server only loads them once it is running
+    if let Listen::Tls(_, cert, key) = &listen {
+        for (what, path) in [("certificate", cert), ("key", key)] {
+            if let Err(e) = File::open(path) {
+                eprintln!("error: failed to read TLS {what} {}: {e}", path.display());
+                std::process::exit(1);
             }
         }
     }
-}
 
-#[tokio::main]
-async fn main() {
-    // Initialize the terminal
-    let mut terminal = ratatui::init();
-    terminal.clear().unwrap();
+    // Open the output file while errors can still be printed, carrying on in memory if it fails
+    let output = args.output.as_deref().and_then(open_output);
+
+    // With --once stdout carries the document and --headless has no UI, so the terminal is left alone
+    let should_quit: ShutdownFlag = Arc::new(AtomicBool::new(false));
+    let result = if args.once || args.headless {
+        run(None, &args, config, bindings, listen, output, should_quit)
+    } else {
+        // Make a panic on any thread restore the terminal and shut the application down
+        install_panic_hook(should_quit.clone());
 
-    // Run the application
-    if let Err(e) = run(terminal) {
-        panic!("error in rendering thread: {:?}", e);
+        // Initialize the terminal, with mouse reporting for clicking on rows
+        let mut terminal = ratatui::init();
+        if let Err(e) = execute!(io::stdout(), EnableMouseCapture) {
+            tracing::warn!(error = %e, "failed to enable mouse capture");
+        }
+
+        // Run the application
+        let result = terminal.clear().and_then(|_| {
+            run(
+                Some(terminal),
+                &args,
+                config,
+                bindings,
+                listen,
+                output,
+                should_quit,
+            )
+        });
+
+        // Restore the terminal state before reporting anything
+        restore_terminal();
+        result
+    };
+
+    if let Err(e) = result {
+        tracing::error!(error = %e, "exiting with error");
+        drop(log_guard);
+        eprintln!("error: {e}");
+        std::process::exit(1);
     }
+}
+
+// The install_panic_hook function wraps the current panic hook so that a panic on any thread,
+// including the draw thread and the server tasks, first sets the shutdown flag and restores the terminal.
+// The previous hook then runs as usual, so the original panic message is printed to a sane shell.
+
+fn install_panic_hook(should_quit: ShutdownFlag) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        should_quit.store(true, Ordering::Relaxed);
+        restore_terminal();
+        previous(info);
+    }));
+}
 
-    // Restore the terminal state
+// Hand the terminal back in the state it was found, turning mouse capture off before ratatui restores the rest
+
+fn restore_terminal() {
+    let _ = execute!(io::stdout(), DisableMouseCapture);
     ratatui::restore();
 }
 
-fn run(terminal: DefaultTerminal) -> io::Result<()> {
-    // Create the application state
-    let app_state = AppState::new();
+// The open_output function opens the --output file for appending, creating it if needed.
+// A file that cannot be opened is reported and skipped so the dashboard still runs in memory-only mode.
+
+fn open_output(path: &Path) -> Option<BufWriter<File>> {
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => Some(BufWriter::new(file)),
+        Err(e) => {
+            eprintln!(
+                "warning: failed to open output {}: {e}, continuing without persistence",
+                path.display()
+            );
+            tracing::error!(path = %path.display(), error = %e, "failed to open output file");
+            None
+        }
+    }
+}
+
+// The init_logging function installs a tracing subscriber writing to the file given by --log-file.
+// The file is rotated daily inside its directory, with the file name used as the prefix of each rotated file.
+// Writes happen on a background worker, so the returned guard has to be kept alive to flush them on exit.
 
-    // Spawn the server thread
-    tokio::spawn(server_thread(app_state.clone()));
+fn init_logging(args: &Args) -> io::Result<Option<WorkerGuard>> {
+    let Some(path) = &args.log_file else {
+        return Ok(None);
+    };
 
-    // Spawn the drawing thread
-    thread::spawn(draw_thread(terminal, app_state.clone()));
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = path
+        .file_name()
+        .ok_or_else(|| io::Error::other(format!("{} is not a file path", path.display())))?;
+    fs::create_dir_all(directory)?;
 
-    // Handle user input
-    take_input()?;
+    let appender = tracing_appender::rolling::daily(directory, prefix);
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_max_level(args.log_level)
+        .init();
+
+    Ok(Some(guard))
+}
+
+// The run function wires the server, draw and input loops together around a shared should_quit flag.
+// Without a terminal it instead waits for the --once document or, with --headless, for the process to be interrupted.
+// Quitting with 'q', pressing Ctrl-C in the terminal, receiving SIGINT or a panic on another thread all set the flag,
+// after which the draw thread is woken through the redraw channel and joined and the server task is cancelled,
+// so main always gets to restore the terminal the same way.
+// A Unix socket file is removed once the server is stopped.
+
+fn run(
+    terminal: Option<DefaultTerminal>,
+    args: &Args,
+    config: Config,
+    bindings: HashMap<KeyCode, Action>,
+    listen: Listen,
+    output: Option<BufWriter<File>>,
+    should_quit: ShutdownFlag,
+) -> io::Result<()> {
+    // Remember the socket file this process created so it can be removed on the way out
+    let socket_path = match listen {
+        Listen::Unix(_) => args.unix_socket.clone(),
+        Listen::Tcp(_) | Listen::Tls(..) => None,
+    };
+
+    // Describe where documents will come from for the screen shown before the first one
+    let source = match (&args.replay, &listen) {
+        (Some(path), _) => format!("replay {}", path.display()),
+        _ if args.stdin => String::from("stdin"),
+        (None, Listen::Tcp(address)) => format!("http://{address}/data"),
+        (None, Listen::Tls(address, ..)) => format!("https://{address}/data"),
+        (None, Listen::Unix(_)) => format!(
+            "unix socket {}",
+            socket_path.as_deref().unwrap_or(Path::new("")).display()
+        ),
+    };
+
+    // Create the application state, handing --once the first document from before anything can arrive
+    let app_state = AppState::new(args.history_capacity);
+    let (received_sender, received) = mpsc::sync_channel(1);
+    {
+        let mut state = app_state.write().unwrap();
+        state.received = args.once.then_some(received_sender);
+        state.aliases = config.aliases.clone();
+        state.numbers = config.numbers;
+        state.symbols = config.symbols.clone();
+        state.ascii = args.ascii;
+        state.source = source;
+        state.dedup = args.dedup;
+        state.dedup_key = args.dedup_key.clone();
+        state.history_ttl = args.history_ttl.map(Duration::from_secs);
+        state.redact = config.redact.clone();
+        state.redact_mode = config.redact_mode;
+        state.export_dir = args.export_dir.clone();
+        state.debounce = Duration::from_millis(args.debounce_ms);
+        state.stale_after = Duration::from_secs(args.stale_secs);
+        state.alert_rules = config.alerts.clone();
+        state.hidden_columns = config.hidden_columns.clone();
+        state.info.fields = config.fields.clone();
+        state.action_keys = action_keys(&bindings);
+        if let Some(saved) = args.persist_state.as_deref().and_then(UiState::load) {
+            saved.restore(&mut state);
+        }
+        // A theme set in the config file wins over the saved one, so changing it there still takes effect
+        if let Some(theme) = config.theme {
+            state.theme = theme;
+        }
+    }
+
+    // Persist received documents when an output file is open
+    let flush = output
+        .is_some()
+        .then(|| tokio::spawn(flush_thread(app_state.clone())));
+    app_state.write().unwrap().output = output;
+
+    // Evict documents past --history-ttl whether or not anything is drawing
+    let expire = args
+        .history_ttl
+        .is_some()
+        .then(|| tokio::spawn(expire_thread(app_state.clone())));
+
+    // Let state changes wake the draw thread
+    let (redraw_sender, redraw_receiver) = mpsc::sync_channel(1);
+    app_state.write().unwrap().redraw = Some(redraw_sender);
+
+    // Spawn the server thread, or play back a saved file or read stdin in its place.
+    // Stdin is read on a plain thread that is left behind on exit, since a blocking read cannot be cancelled.
+    let refresh = Duration::from_millis(args.refresh_ms);
+    let server = match &args.replay {
+        Some(path) => Some(tokio::spawn(replay_thread(
+            app_state.clone(),
+            path.clone(),
+            refresh,
+            args.looping,
+        ))),
+        None if args.stdin => {
+            let app_state = app_state.clone();
+            thread::spawn(move || stdin_thread(app_state));
+            None
+        }
+        None => Some(tokio::spawn(server_thread(
+            app_state.clone(),
+            listen,
+            args.auth_token.clone(),
+            args.max_body_bytes,
+            args.rate_limit,
+        ))),
+    };
+
+    // Poll the cluster when one is configured, --es-query into the default channel and each configured
+    // query into its own
+    let default_poll = args.es_query.clone().map(|query| PollSpec {
+        name: DEFAULT_CHANNEL.to_string(),
+        query,
+        interval: None,
+    });
+    let polls: Vec<JoinHandle<()>> = match &args.es_url {
+        Some(url) => default_poll
+            .into_iter()
+            .chain(config.polls.iter().cloned())
+            .map(|poll| {
+                let credentials = args
+                    .es_user
+                    .clone()
+                    .map(|user| (user, args.es_pass.clone()));
+                tokio::spawn(poll_thread(
+                    app_state.clone(),
+                    url.clone(),
+                    poll.name,
+                    poll.query,
+                    Duration::from_secs(poll.interval.unwrap_or(args.es_interval)),
+                    credentials,
+                ))
+            })
+            .collect(),
+        None => vec![],
+    };
+
+    // Set the shutdown flag when the process is interrupted
+    let signal = tokio::spawn(signal_thread(should_quit.clone()));
+
+    let result = match terminal {
+        Some(terminal) => {
+            // Spawn the drawing thread
+            let view = SharedView::default();
+            let draw = thread::spawn(draw_thread(
+                terminal,
+                app_state.clone(),
+                view.clone(),
+                config,
+                should_quit.clone(),
+                redraw_receiver,
+                refresh,
+            ));
+
+            // Handle user input
+            let input_result = take_input(app_state.clone(), view, &bindings, &should_quit);
+
+            // Save what to restore next time, carrying on with the exit if it cannot be written
+            if let Some(path) = &args.persist_state {
+                let state = app_state.read().unwrap_or_else(PoisonError::into_inner);
+                if let Err(e) = UiState::capture(&state).save(path) {
+                    tracing::warn!(path = %path.display(), error = %e, "failed to save state file");
+                }
+            }
+
+            // Stop the other threads before handing the terminal back, even if a panic poisoned the state
+            should_quit.store(true, Ordering::Relaxed);
+            app_state
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .request_redraw();
+            let draw_result = draw
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("draw thread panicked")));
+            input_result.and(draw_result).map(|_| None)
+        }
+        None if args.headless => {
+            wait_for_shutdown(server.as_ref(), &app_state, &should_quit).map(|_| None)
+        }
+        None => wait_for_document(&received, args.timeout, &app_state, &should_quit).map(Some),
+    };
+    if let Some(server) = server {
+        server.abort();
+    }
+    signal.abort();
+    if let Some(flush) = flush {
+        flush.abort();
+    }
+    if let Some(expire) = expire {
+        expire.abort();
+    }
+    for poll in polls {
+        poll.abort();
+    }
+    if let Some(path) = socket_path {
+        if let Err(e) = fs::remove_file(&path) {
+            tracing::warn!(path = %path.display(), error = %e, "failed to remove unix socket");
+        }
+    }
+    app_state
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .flush_output();
+
+    // Print the document --once waited for now that the server is down
+    if let Some(document) = result? {
+        println!("{document}");
+    }
+    Ok(())
+}
+
+// The wait_for_document function blocks --once until the first document arrives, the timeout
+// passes or the process is interrupted. It returns the mapped document as a JSON array holding
+// one object per row, as built by mapped_rows.
+
+fn wait_for_document(
+    receiver: &Receiver<Log>,
+    timeout: Option<u64>,
+    app_state: &SharedAppState,
+    should_quit: &AtomicBool,
+) -> io::Result<String> {
+    let deadline = timeout.map(|seconds| Instant::now() + Duration::from_secs(seconds));
+
+    // Wake regularly to notice Ctrl-C and the deadline
+    let log = loop {
+        if let Ok(log) = receiver.recv_timeout(INPUT_POLL_INTERVAL) {
+            break log;
+        }
+        if should_quit.load(Ordering::Relaxed) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "interrupted before a document was received",
+            ));
+        }
+        if let Some(error) = app_state.read().unwrap().server_error.clone() {
+            return Err(io::Error::other(error));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "no document received within {}s",
+                    timeout.unwrap_or_default()
+                ),
+            ));
+        }
+    };
+
+    serde_json::to_string(&mapped_rows(&log)).map_err(io::Error::other)
+}
+
+// The wait_for_shutdown function blocks --headless until the process is interrupted.
+// A server that stops on its own, such as one that failed to bind its address, ends the wait with an error
+// instead of leaving a collector running with nothing listening. The bind error is reported when there is one.
+
+fn wait_for_shutdown(
+    server: Option<&JoinHandle<()>>,
+    app_state: &SharedAppState,
+    should_quit: &AtomicBool,
+) -> io::Result<()> {
+    while !should_quit.load(Ordering::Relaxed) {
+        if server.is_some_and(JoinHandle::is_finished) {
+            let error = app_state.read().unwrap().server_error.clone();
+            return Err(io::Error::other(
+                error.unwrap_or_else(|| "server stopped unexpectedly".to_string()),
+            ));
+        }
+        thread::sleep(INPUT_POLL_INTERVAL);
+    }
     Ok(())
 }
 
+// The signal_thread function waits for Ctrl-C delivered as a signal (for example `kill -INT`)
+// and sets the shutdown flag so every loop winds down through the normal path.
+
+async fn signal_thread(should_quit: ShutdownFlag) {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        should_quit.store(true, Ordering::Relaxed);
+    }
+}
+
 // The draw_thread function is responsible for rendering the UI.
-// It takes a terminal and a shared application state as arguments.
+// It takes a terminal, a shared application state, the loaded config, the shutdown flag,
+// the redraw channel and the refresh interval as arguments.
 // The function returns a closure that will be executed in a separate thread.
 // Inside the closure, it calls the draw_ui function to update the terminal with the current state.
-// If an error occurs during the UI drawing process, it will be printed to the standard error output.
+// If an error occurs during the UI drawing process, or drawing panics, the shutdown flag is set so the rest of the
+// application exits, and the error is returned through the thread's join handle.
 
-fn draw_thread(terminal: TerminalBackend, app_state_draw: SharedAppState) -> impl FnOnce() {
+fn draw_thread(
+    terminal: TerminalBackend,
+    app_state_draw: SharedAppState,
+    view: SharedView,
+    config: Config,
+    should_quit: ShutdownFlag,
+    redraw: Receiver<()>,
+    refresh: Duration,
+) -> impl FnOnce() -> io::Result<()> {
     move || {
-        if let Err(e) = draw_ui(terminal, app_state_draw) {
-            eprintln!("Error in draw_ui: {:?}", e);
-        }
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            draw_ui(
+                terminal,
+                app_state_draw,
+                &view,
+                &config,
+                &should_quit,
+                &redraw,
+                refresh,
+            )
+        }))
+        .unwrap_or_else(|_| Err(io::Error::other("draw thread panicked")));
+        should_quit.store(true, Ordering::Relaxed);
+        result
     }
 }
 
-// The server_thread function is responsible for handling incoming HTTP requests.
-// It takes a shared application state as an argument and runs an asynchronous server using Warp.
-// The function defines a route for receiving logs via a POST request to the "/data" path.
-// When a log is received, it updates the application state with the new log and responds with the current document.
-// The server listens on the specified address and port, and runs indefinitely until the application is terminated.
+// The replay_thread function stands in for the server when --replay is given.
+// It reads the NDJSON file line by line and feeds one document into the application state per
+// refresh interval, as if each had just been posted. Lines that fail to parse are skipped.
+// At the end of the file it starts over when looping, and otherwise leaves the last document on screen.
 
-async fn server_thread(app_state_server: SharedAppState) {
-    // Define the route for receiving logs
-    let logs_route = warp::post()
-        .and(warp::path("data"))
-        .and(warp::body::json())
-        .map(move |log: Log| {
-            let mut state = app_state_server.lock().unwrap();
-            state.update_log(log);
-            warp::reply::json(&state.current_document)
-        });
+async fn replay_thread(
+    app_state: SharedAppState,
+    path: PathBuf,
+    interval: Duration,
+    looping: bool,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::error!(path = %path.display(), error = %e, "failed to open replay file");
+                app_state.write().unwrap().last_error = Some(format!("failed to open replay: {e}"));
+                return;
+            }
+        };
+
+        let mut lines = tokio::io::BufReader::new(file).lines();
+        let mut replayed = 0;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Log>(&line) {
+                Ok(log) => {
+                    ticker.tick().await;
+                    app_state.write().unwrap().update_log(DEFAULT_CHANNEL, log);
+                    replayed += 1;
+                }
+                Err(e) => tracing::warn!(error = %e, "skipping malformed replay line"),
+            }
+        }
 
-    // Start the server
-    let address = SocketAddrV4::new(Ipv4Addr::from(ADDRESS), PORT);
-    warp::serve(logs_route).run(address).await;
+        // Stop rather than spin when there is nothing to replay
+        if !looping || replayed == 0 {
+            break;
+        }
+    }
+    app_state.write().unwrap().notify("replay finished");
+}
+
+// The stdin_thread function stands in for the server when --stdin is given, reading documents piped in.
+// Lines are gathered until they parse as a Log, so both NDJSON and pretty-printed JSON documents work,
+// and a document that is malformed rather than unfinished is skipped.
+// At the end of input the last document stays on screen, since the pipe may well have been one-shot.
+
+fn stdin_thread(app_state: SharedAppState) {
+    let mut pending = String::new();
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to read stdin");
+                app_state.write().unwrap().last_error = Some(format!("failed to read stdin: {e}"));
+                return;
+            }
+        };
+        if pending.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        pending.push_str(&line);
+        pending.push('\n');
+        match serde_json::from_str::<Log>(&pending) {
+            Ok(log) => app_state.write().unwrap().update_log(DEFAULT_CHANNEL, log),
+            // The document carries on over the next lines
+            Err(e) if e.is_eof() => continue,
+            Err(e) => tracing::warn!(error = %e, "skipping malformed stdin document"),
+        }
+        pending.clear();
+    }
+    if !pending.is_empty() {
+        tracing::warn!("stdin ended partway through a document");
+    }
+    app_state.write().unwrap().notify("end of stdin");
 }
 
 // The take_input function is responsible for handling user input in a loop.
-// It continuously reads events from the terminal and checks for key presses.
-// If the 'q' key is pressed, the function breaks out of the loop and returns,
-// effectively allowing the user to exit the application.
+// It continuously reads events from the terminal and checks for key presses,
+// looking each one up in the key bindings to find the action to carry out with perform_action.
+// With the default bindings Up/Down (or k/j) move the row selection stored on the shared application state,
+// and '[' / ']' step backward and forward through the received history.
+// Left/Right (or h/l) scroll the columns, '<' / '>' move the column selection and
+// 's' cycles sorting on the selected column.
+// 'c' clears the dashboard the same way the "/clear" route does, and 'y' copies the selected row.
+// 'a' acknowledges a raised alert, Tab switches to the next channel, 'v' toggles the feed view,
+// 'W' lists the type warnings, 'e' exports the document on screen to CSV, 'd' toggles diff mode
+// and '1' to '5' jump to the recent documents shown as tabs.
+// Enter opens the inspector for the selected row, Shift-J / Shift-K and PageDown / PageUp scroll it,
+// 'w' toggles wrapping its long values and Esc closes it. 'C' opens the column picker, handled by handle_columns_key.
+// Mouse events are handed to handle_mouse.
+// '/' switches to filter mode, where key presses are handed to handle_filter_key instead.
+// ':' or Ctrl-P opens the command palette, handled by handle_palette_key, whose chosen action is carried out the same way.
+// Handled keys ask the draw thread to redraw straight away.
+// If the quit key or Ctrl-C is pressed, or another thread sets the shutdown flag,
+// the function breaks out of the loop and returns, effectively allowing the user to exit the application.
+// Events are polled with a short timeout so the flag is noticed without waiting for a key press.
 // The function returns a Result<(), io::Error> to handle any potential I/O errors
 // that may occur during the event reading process.
 
-fn take_input() -> Result<(), io::Error> {
-    loop {
+fn take_input(
+    app_state: SharedAppState,
+    view: SharedView,
+    bindings: &HashMap<KeyCode, Action>,
+    should_quit: &AtomicBool,
+) -> Result<(), io::Error> {
+    // Opened on first use and kept alive, since on X11 the copied text lives only as long as it does
+    let mut clipboard = None;
+
+    while !should_quit.load(Ordering::Relaxed) {
+        // Wait briefly for user input so the shutdown flag gets checked regularly
+        if !event::poll(INPUT_POLL_INTERVAL)? {
+            continue;
+        }
+
         // Read user input
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                // Exit the loop if 'q' is pressed
-                if let KeyCode::Char('q') = key.code {
+        match event::read()? {
+            // Redraw straight away so the layout fits the new size
+            Event::Resize(..) => app_state.read().unwrap().request_redraw(),
+            Event::Mouse(mouse) => {
+                let mut state = app_state.write().unwrap();
+                if handle_mouse(&mut state, &view.lock().unwrap(), mouse) {
+                    state.request_redraw();
+                }
+            }
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                let mut state = app_state.write().unwrap();
+
+                // Ctrl-C exits no matter which mode is active
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    break;
+                }
+
+                // Ctrl-P opens the command palette like its bound keys, and an entry chosen in it is carried out
+                // as if its key had been pressed
+                let control = key.modifiers.contains(KeyModifiers::CONTROL);
+                let handled = match state.input_mode {
+                    InputMode::Normal if control && key.code == KeyCode::Char('p') => {
+                        Ok(Action::Palette)
+                    }
+                    InputMode::Normal => bindings.get(&key.code).copied().ok_or(false),
+                    InputMode::Palette => handle_palette_key(&mut state, key.code).ok_or(true),
+                    InputMode::Filter => Err(handle_filter_key(&mut state, key.code)),
+                    InputMode::Search => Err(handle_search_key(&mut state, key.code)),
+                    InputMode::Results => Err(handle_results_key(&mut state, key.code)),
+                    InputMode::Columns => Err(handle_columns_key(&mut state, key.code)),
+                    InputMode::Highlight => Err(handle_highlight_key(&mut state, key.code)),
+                };
+                let action = match handled {
+                    Ok(action) => action,
+                    Err(handled) => {
+                        if handled {
+                            state.request_redraw();
+                        }
+                        continue;
+                    }
+                };
+
+                let mut view = view.lock().unwrap();
+                if perform_action(&mut state, &mut view, action, &mut clipboard).is_break() {
                     break;
                 }
+
+                // Show the result of the key press straight away
+                state.request_redraw();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// The perform_action function carries out an action on the dashboard, whether its key was pressed or it was
+// picked from the command palette. It breaks when the action is to quit.
+
+fn perform_action(
+    state: &mut AppState,
+    view: &mut View,
+    action: Action,
+    clipboard: &mut Option<Clipboard>,
+) -> ControlFlow<()> {
+    match action {
+        Action::Quit => return ControlFlow::Break(()),
+        Action::Filter => state.input_mode = InputMode::Filter,
+        Action::Search => state.input_mode = InputMode::Search,
+        Action::Down if state.feed => state.scroll_feed(-1),
+        Action::Up if state.feed => state.scroll_feed(1),
+        Action::Top if state.feed => state.scroll_feed(isize::MAX),
+        Action::Bottom if state.feed => state.follow_feed(),
+        Action::Down => state.select_next(),
+        Action::Up => state.select_previous(),
+        Action::Top => state.select_first(),
+        Action::Bottom => state.select_last(),
+        Action::Left => scroll_columns(state, view, -1),
+        Action::Right => scroll_columns(state, view, 1),
+        Action::ColumnLeft => state.move_column_selection(-1),
+        Action::ColumnRight => state.move_column_selection(1),
+        Action::Sort => state.cycle_sort(),
+        Action::Clear => state.clear(),
+        Action::Copy => copy_selected_row(state, clipboard),
+        Action::Split => state.split = !state.split,
+        Action::Expand => state.expanded = !state.expanded,
+        Action::Pause => state.toggle_pause(),
+        Action::Histogram => state.histogram = !state.histogram,
+        Action::MoreBuckets if state.histogram => {
+            state.histogram_buckets = (state.histogram_buckets + 1).min(HISTOGRAM_MAX_BUCKETS)
+        }
+        Action::FewerBuckets if state.histogram => {
+            state.histogram_buckets = state.histogram_buckets.saturating_sub(1).max(1)
+        }
+        Action::MoreBuckets | Action::FewerBuckets => {}
+        Action::Older => state.history_older(),
+        Action::Newer => state.history_newer(),
+        Action::HistoryOrder => state.cycle_history_order(),
+        Action::TimeRange => state.cycle_time_range(),
+        Action::Acknowledge => state.acknowledge_alert(),
+        Action::NextChannel => state.next_channel(),
+        Action::Feed => state.feed = !state.feed,
+        Action::Warnings => state.warnings_panel = !state.warnings_panel,
+        Action::Export => export_csv(state),
+        Action::Diff => state.diff = !state.diff,
+        Action::Recent1 => state.show_recent(0),
+        Action::Recent2 => state.show_recent(1),
+        Action::Recent3 => state.show_recent(2),
+        Action::Recent4 => state.show_recent(3),
+        Action::Recent5 => state.show_recent(4),
+        Action::Inspector => state.inspector = !state.inspector,
+        Action::CloseInspector => state.inspector = false,
+        Action::InspectorDown => state.inspector_scroll = state.inspector_scroll.saturating_add(1),
+        Action::InspectorUp => state.inspector_scroll = state.inspector_scroll.saturating_sub(1),
+        Action::InspectorPageDown => {
+            state.inspector_scroll = state.inspector_scroll.saturating_add(view.inspector_page())
+        }
+        Action::InspectorPageUp => {
+            state.inspector_scroll = state.inspector_scroll.saturating_sub(view.inspector_page())
+        }
+        Action::Wrap => state.wrap = !state.wrap,
+        Action::Sizes => state.sizes = !state.sizes,
+        Action::Help => state.help = !state.help,
+        Action::Columns => {
+            state.column_picker = state.selected_col;
+            state.input_mode = InputMode::Columns;
+        }
+        Action::Highlight => state.input_mode = InputMode::Highlight,
+        Action::Palette => {
+            state.palette_query.clear();
+            state.palette_selected = 0;
+            state.input_mode = InputMode::Palette;
+        }
+        Action::Reveal => state.toggle_reveal(),
+    }
+    ControlFlow::Continue(())
+}
+
+// Scroll the table horizontally by a number of columns, carrying the column selection along so it stays in view
+
+fn scroll_columns(state: &mut AppState, view: &mut View, delta: isize) {
+    let last = state.displayed_document().columns.len().saturating_sub(1);
+    view.col_offset = view.col_offset.saturating_add_signed(delta).min(last);
+    state.scroll_columns(delta);
+}
+
+// The handle_mouse function selects the table row under a left click and moves the selection with the
+// scroll wheel. Click positions are translated using the table area and scroll offset from the last frame,
+// skipping the border and header line. It reports whether anything changed.
+
+fn handle_mouse(state: &mut AppState, view: &View, mouse: MouseEvent) -> bool {
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            let area = view.area;
+            let first_row = area.y + 2;
+            let inside = (area.x..area.right()).contains(&mouse.column)
+                && (first_row..area.bottom().saturating_sub(1)).contains(&mouse.row);
+            if !inside {
+                return false;
             }
+            let position = view.table.offset() + usize::from(mouse.row - first_row);
+            let Some(&row) = state.visible_rows().get(position) else {
+                return false;
+            };
+            state.selected_row = Some(row);
+            state.inspector_scroll = 0;
         }
+        MouseEventKind::ScrollDown if state.feed => state.scroll_feed(-1),
+        MouseEventKind::ScrollUp if state.feed => state.scroll_feed(1),
+        MouseEventKind::ScrollDown => state.select_next(),
+        MouseEventKind::ScrollUp => state.select_previous(),
+        _ => return false,
     }
+    true
+}
+
+// The copy_selected_row function copies the selected row, as a JSON object keyed by column name,
+// to the system clipboard. When no clipboard is available the JSON is written to a file in the
+// temp directory instead, and either way the outcome is reported in the status bar.
+
+fn copy_selected_row(state: &mut AppState, clipboard: &mut Option<Clipboard>) {
+    let Some(json) = state.selected_row_json() else {
+        state.notify("no row selected");
+        return;
+    };
+
+    let message = match copy_to_clipboard(clipboard, &json) {
+        Ok(()) => String::from("copied!"),
+        Err(e) => {
+            tracing::warn!(error = %e, "clipboard unavailable");
+            match save_to_temp_file(&json) {
+                Ok(path) => format!("no clipboard, saved to {}", path.display()),
+                Err(e) => format!("copy failed: {e}"),
+            }
+        }
+    };
+    state.notify(message);
+}
+
+// The export_csv function writes the document on screen to a timestamped CSV file in the export directory,
+// with the column names as the header and one record per row. The outcome is reported in the status bar.
+
+fn export_csv(state: &mut AppState) {
+    let name = format!(
+        "dashview-export-{}.csv",
+        Utc::now().format("%Y%m%d-%H%M%S%.3f")
+    );
+    let path = state.export_dir.join(name);
+    let message = match write_csv(&path, state.displayed_document()) {
+        Ok(()) => {
+            tracing::info!(path = %path.display(), "exported csv");
+            format!("exported to {}", path.display())
+        }
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "csv export failed");
+            format!("export failed: {e}")
+        }
+    };
+    state.notify(message);
+}
+
+// Write a document as CSV. Strings are written as they are, nulls as empty cells,
+// and objects and arrays as compact JSON
+
+fn write_csv(path: &Path, log: &Log) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(log.columns.iter().map(|column| column.name.as_str()))?;
+    for row in &log.cells {
+        writer.write_record(row.iter().map(|cell| match cell {
+            TypedCell::Null => String::new(),
+            cell => cell.plain_text(),
+        }))?;
+    }
+    writer.flush()?;
     Ok(())
 }
 
+// Put text on the clipboard, opening it first if this is the first copy
+
+fn copy_to_clipboard(clipboard: &mut Option<Clipboard>, text: &str) -> Result<(), arboard::Error> {
+    let clipboard = match clipboard {
+        Some(clipboard) => clipboard,
+        None => clipboard.insert(Clipboard::new()?),
+    };
+    clipboard.set_text(text)
+}
+
+// Write copied text to a timestamped file in the temp directory and return its path
+
+fn save_to_temp_file(text: &str) -> io::Result<PathBuf> {
+    let name = format!(
+        "dashview-row-{}.json",
+        Utc::now().format("%Y%m%d-%H%M%S%.3f")
+    );
+    let path = std::env::temp_dir().join(name);
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+// The handle_filter_key function edits the filter while filter mode is active.
+// Characters extend the query and Backspace removes the last one, Enter keeps the filter and
+// returns to normal mode, and Esc clears it before returning. It reports whether the key was used.
+
+fn handle_filter_key(state: &mut AppState, code: KeyCode) -> bool {
+    match code {
+        KeyCode::Char(c) => state.filter.push(c),
+        KeyCode::Backspace => {
+            state.filter.pop();
+        }
+        KeyCode::Enter => state.input_mode = InputMode::Normal,
+        KeyCode::Esc => {
+            state.filter.clear();
+            state.input_mode = InputMode::Normal;
+        }
+        _ => return false,
+    }
+    true
+}
+
+// The handle_palette_key function filters and picks from the command palette while it is open.
+// Characters extend the query and Backspace removes the last one, Up / Down move through the matching
+// actions and Esc closes the palette. Enter closes it too and returns the highlighted action for take_input
+// to carry out, giving None for every other key.
+
+fn handle_palette_key(state: &mut AppState, code: KeyCode) -> Option<Action> {
+    match code {
+        KeyCode::Char(c) => {
+            state.palette_query.push(c);
+            state.palette_selected = 0;
+        }
+        KeyCode::Backspace => {
+            state.palette_query.pop();
+            state.palette_selected = 0;
+        }
+        KeyCode::Down => {
+            let last = state.palette_entries().len().saturating_sub(1);
+            state.palette_selected = (state.palette_selected + 1).min(last);
+        }
+        KeyCode::Up => state.palette_selected = state.palette_selected.saturating_sub(1),
+        KeyCode::Enter => {
+            let chosen = state
+                .palette_entries()
+                .get(state.palette_selected)
+                .map(|info| info.action);
+            state.input_mode = InputMode::Normal;
+            return chosen;
+        }
+        KeyCode::Esc => state.input_mode = InputMode::Normal,
+        _ => {}
+    }
+    None
+}
+
+// The handle_highlight_key function edits the highlight pattern while highlight mode is active.
+// Characters extend the pattern and Backspace removes the last one. Enter compiles it and returns to
+// normal mode, or stays in the prompt showing the error when it is not a valid regex; an empty pattern
+// turns highlighting off. Esc turns highlighting off and returns. It reports whether the key was used.
+
+fn handle_highlight_key(state: &mut AppState, code: KeyCode) -> bool {
+    match code {
+        KeyCode::Char(c) => state.highlight_query.push(c),
+        KeyCode::Backspace => {
+            state.highlight_query.pop();
+        }
+        KeyCode::Enter if state.highlight_query.is_empty() => {
+            state.highlight = None;
+            state.input_mode = InputMode::Normal;
+        }
+        KeyCode::Enter => match Regex::new(&state.highlight_query) {
+            Ok(pattern) => {
+                state.highlight = Some(pattern);
+                state.input_mode = InputMode::Normal;
+            }
+            // Regex errors spell the problem out on their last line, under a picture of the pattern
+            Err(e) => {
+                let message = e.to_string();
+                let reason = message.lines().last().unwrap_or_default();
+                state.highlight_error = Some(reason.trim_start_matches("error: ").to_string());
+                return true;
+            }
+        },
+        KeyCode::Esc => {
+            state.highlight_query.clear();
+            state.highlight = None;
+            state.input_mode = InputMode::Normal;
+        }
+        _ => return false,
+    }
+    state.highlight_error = None;
+    true
+}
+
+// The handle_search_key function edits the history search query while search mode is active.
+// Characters extend the query, Backspace removes the last one and Tab toggles exact matching.
+// Enter runs the search and shows its results, and Esc returns to normal mode. It reports whether the key was used.
+
+fn handle_search_key(state: &mut AppState, code: KeyCode) -> bool {
+    match code {
+        KeyCode::Char(c) => state.search.query.push(c),
+        KeyCode::Backspace => {
+            state.search.query.pop();
+        }
+        KeyCode::Tab => state.search.exact = !state.search.exact,
+        KeyCode::Enter => {
+            state.run_search();
+            state.input_mode = InputMode::Results;
+        }
+        KeyCode::Esc => state.input_mode = InputMode::Normal,
+        _ => return false,
+    }
+    true
+}
+
+// The handle_results_key function moves through the search results while they are shown.
+// Enter opens the highlighted result and returns to normal mode, Esc closes the results,
+// and 'f' goes back to editing the query. It reports whether the key was used.
+
+fn handle_results_key(state: &mut AppState, code: KeyCode) -> bool {
+    let last = state.search.hits.len().saturating_sub(1);
+    match code {
+        KeyCode::Down | KeyCode::Char('j') => {
+            state.search.selected = (state.search.selected + 1).min(last)
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            state.search.selected = state.search.selected.saturating_sub(1)
+        }
+        KeyCode::Enter => {
+            state.open_search_result();
+            state.input_mode = InputMode::Normal;
+        }
+        KeyCode::Esc => state.input_mode = InputMode::Normal,
+        KeyCode::Char('f') => state.input_mode = InputMode::Search,
+        _ => return false,
+    }
+    true
+}
+
+// The handle_columns_key function moves through the column picker while it is open.
+// Space hides or shows the column under the cursor, and Esc, Enter or 'C' closes the picker.
+// It reports whether the key was used.
+
+fn handle_columns_key(state: &mut AppState, code: KeyCode) -> bool {
+    let last = state.displayed_document().columns.len().saturating_sub(1);
+    match code {
+        KeyCode::Down | KeyCode::Char('j') => {
+            state.column_picker = (state.column_picker + 1).min(last)
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            state.column_picker = state.column_picker.saturating_sub(1)
+        }
+        KeyCode::Char(' ') => state.toggle_column(state.column_picker),
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('C') => {
+            state.input_mode = InputMode::Normal;
+            state.clamp_selection();
+        }
+        _ => return false,
+    }
+    true
+}
+
 // The draw_ui function is responsible for rendering the user interface in a loop.
-// It takes a terminal and a shared application state as arguments.
-// Inside the loop, it sleeps for a short duration before redrawing the UI to avoid excessive CPU usage.
-// The function locks the application state to access the mapped document and formats the keys to display.
-// It creates a Paragraph widget with the formatted message and renders it on the terminal frame.
+// It takes a terminal, a shared application state, the config and the redraw signalling as arguments.
+// Inside the loop, it waits on the redraw channel for at most the refresh interval, so new data and
+// key presses show up immediately while an idle feed is still redrawn periodically.
+// It returns as soon as the shutdown flag is set (run sends a redraw request to cut the wait short).
+// The function locks the application state for the duration of each frame and hands it to render_frame.
 // If an error occurs during the drawing process, it will be propagated as an io::Result error.
 
-fn draw_ui(mut terminal: DefaultTerminal, app_state: SharedAppState) -> io::Result<()> {
+fn draw_ui(
+    mut terminal: DefaultTerminal,
+    app_state: SharedAppState,
+    view: &Mutex<View>,
+    config: &Config,
+    should_quit: &AtomicBool,
+    redraw: &Receiver<()>,
+    refresh: Duration,
+) -> io::Result<()> {
+    let mut rung = 0; // Alerts the bell has been rung for
     loop {
-        // Sleep for a short duration before redrawing
-        thread::sleep(Duration::from_millis(2500));
+        // Wait for a change or the refresh interval before redrawing,
+        // waking early to take down a status message once it expires, to flash a raised alert
+        // or to show a document the debounce window held back
+        let state = app_state.read().unwrap();
+        let mut wait = state
+            .status_message
+            .as_ref()
+            .map(|(_, set)| {
+                (*set + STATUS_MESSAGE_DURATION).saturating_duration_since(Instant::now())
+            })
+            .filter(|remaining| !remaining.is_zero())
+            .map_or(refresh, |remaining| remaining.min(refresh));
+        if state.alert.is_some() {
+            wait = wait.min(ALERT_FLASH_INTERVAL);
+        }
+        if let Some(pending) = state.pending_wait() {
+            wait = wait.min(pending);
+        }
+        drop(state);
+        if let Err(mpsc::RecvTimeoutError::Disconnected) = redraw.recv_timeout(wait) {
+            return Ok(());
+        }
+        if should_quit.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        // Bring the state up to date for this frame, holding the write lock no longer than that
+        let raised = {
+            let mut state = app_state.write().unwrap();
+            state.show_pending();
+            state.update_changes(&config.fields);
+            state.alerts_raised
+        };
 
-        // Draw the UI
+        // Draw the UI, reading the state alongside the server and input threads
         terminal
             .draw(|frame| {
-                let map = { &app_state.lock().unwrap().mapped_document };
-
-                // Define the keys to display
-                let keys: Vec<&str> = vec![
-                    TIMESTAMP,
-                    AGENT_ID,
-                    HOST_NAME,
-                    HOST_OS_NAME,
-                    USER_NAME,
-                    HOST_IP,
-                ];
-
-                // Format the message to display
-                let message = keys
-                    .iter()
-                    .map(|item| format_by_key(item, map))
-                    .collect::<String>();
-
-                // Create and render the widget
-                let widget = Paragraph::new(format!("{message}"));
-                frame.render_widget(widget, frame.area());
+                // Explain a terminal too small for the layout instead of drawing it broken
+                let area = frame.area();
+                if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+                    render_too_small(frame);
+                    return;
+                }
+                let state = app_state.read().unwrap();
+                render_frame(frame, &state, &mut view.lock().unwrap(), config);
             })
             .map(|_| ())?;
+
+        // Ring the bell once for each newly raised alert
+        let ring = raised > rung;
+        rung = raised;
+        if ring && config.bell {
+            let mut stdout = io::stdout();
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+    }
+}
+
+// Say the terminal is too small, centred vertically, wrapping onto more lines when it is narrow
+
+fn render_too_small(frame: &mut Frame) {
+    let area = frame.area();
+    let message = format!("terminal too small (min {MIN_WIDTH}x{MIN_HEIGHT})");
+    let lines = (message.chars().count() as u16).div_ceil(area.width.max(1));
+    let [_, middle, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(lines),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    let paragraph = Paragraph::new(message).centered().wrap(Wrap { trim: true });
+    frame.render_widget(paragraph, middle);
+}
+
+// The render_help_footer function hints at the most useful keys for what currently has them: the table,
+// the inspector, or the prompt or picker being used. Bound actions show their shortest key as configured,
+// and the fixed keys of the prompts and pickers are written out.
+
+fn render_help_footer(frame: &mut Frame, area: Rect, state: &AppState) {
+    let fixed: &[(&str, &str)] = match state.input_mode {
+        InputMode::Normal => &[],
+        InputMode::Filter | InputMode::Highlight => &[("enter", "apply"), ("esc", "cancel")],
+        InputMode::Search => &[("enter", "search"), ("esc", "cancel")],
+        InputMode::Results => &[
+            ("j/k", "move"),
+            ("enter", "show"),
+            ("f", "edit"),
+            ("esc", "close"),
+        ],
+        InputMode::Columns => &[("j/k", "move"), ("space", "show/hide"), ("esc", "close")],
+        InputMode::Palette => &[("up/down", "move"), ("enter", "run"), ("esc", "close")],
+    };
+    let hints: Vec<(String, &str)> = if state.input_mode == InputMode::Normal {
+        let bound: &[(&[Action], &str)] = if state.inspector {
+            &INSPECTOR_HINTS
+        } else {
+            &TABLE_HINTS
+        };
+        bound
+            .iter()
+            .filter_map(|(actions, label)| Some((state.hint_keys(actions)?, *label)))
+            .collect()
+    } else {
+        fixed
+            .iter()
+            .map(|(key, label)| (key.to_string(), *label))
+            .collect()
+    };
+
+    let spans: Vec<Span> = hints
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, (key, label))| {
+            let gap = if i == 0 { " " } else { "  " };
+            [
+                Span::raw(gap),
+                Span::raw(key).bold(),
+                Span::raw(format!(" {label}")).dim(),
+            ]
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+// Say where documents are awaited, centred in the area, with a reminder of the key that quits
+
+fn render_waiting(frame: &mut Frame, area: Rect, state: &AppState) {
+    let [_, middle, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(3),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    // Ctrl-C quits whatever the config file binds
+    let quit = state
+        .hint_keys(&[Action::Quit])
+        .unwrap_or_else(|| String::from("Ctrl-C"));
+    let lines = vec![
+        Line::from(format!(
+            "Waiting for data on {} {}",
+            state.source,
+            if state.ascii { "..." } else { "…" }
+        ))
+        .bold(),
+        Line::default(),
+        Line::from(format!("Press {quit} to quit")).dim(),
+    ];
+    let paragraph = Paragraph::new(lines).centered().wrap(Wrap { trim: true });
+    frame.render_widget(paragraph, middle);
+}
+
+// The render_frame function lays out a single frame of the dashboard.
+// The top pane summarises the configured fields of the mapped document, or every column in the
+// order it was received when the field order is AsReceived. In split mode it is divided into two panes
+// showing the two most recent documents side by side. The rest of the screen is given to a table
+// holding every row of the current document, rendered with the TableState kept on the View
+// so its scroll position survives redraws.
+
+fn render_frame(frame: &mut Frame, state: &AppState, view: &mut View, config: &Config) {
+    // Put the banner above everything else when one is configured, leaving the whole frame to the rest otherwise
+    let area = match &config.title {
+        Some(template) => {
+            let [banner_area, area] =
+                Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(frame.area());
+            render_banner(frame, banner_area, state, template);
+            area
+        }
+        None => frame.area(),
+    };
+
+    // Keep the bottom line for key hints when they are on
+    let area = if state.help {
+        let [area, help_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+        render_help_footer(frame, help_area, state);
+        area
+    } else {
+        area
+    };
+
+    // Until something arrives, say where it is awaited instead of showing an empty dashboard
+    if !state.has_data {
+        let [waiting_area, status_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+        render_waiting(frame, waiting_area, state);
+        render_status_bar(frame, status_area, state, None);
+        return;
+    }
+
+    // The feed view takes everything above the status bar
+    if state.feed {
+        let [feed_area, status_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+        render_feed(frame, feed_area, state, &config.feed_template);
+        render_status_bar(frame, status_area, state, None);
+        return;
+    }
+
+    // Pick the documents for the summary panes, which all show the selected row (the first when none is selected)
+    let row = state.selected_row.unwrap_or(0);
+    let panes = if state.split {
+        let latest = state.get_historical(0).unwrap_or(&state.current_document);
+        let previous = state.get_historical(1);
+        vec![
+            ("Fields (latest)", Some(latest)),
+            ("Fields (previous)", previous),
+        ]
+    } else {
+        vec![("Fields", Some(state.displayed_document()))]
+    };
+    let keys: Vec<Vec<String>> = panes
+        .iter()
+        .map(|(_, document)| {
+            let mut keys = summary_keys(config, *document);
+            keys.retain(|key| !state.hidden_columns.contains(key));
+            keys
+        })
+        .collect();
+    let rows = keys.iter().map(Vec::len).max().unwrap_or_default();
+
+    // Split the frame into the tab bar once anything has arrived, the summary pane, the table,
+    // the filter line when one is in use and the status bar
+    let editing = matches!(
+        state.input_mode,
+        InputMode::Filter | InputMode::Search | InputMode::Highlight | InputMode::Palette
+    );
+    let show_filter = editing || !state.filter.is_empty();
+    let [tabs_area, summary_area, table_area, filter_area, status_area] = Layout::vertical([
+        Constraint::Length(u16::from(state.history_len() > 0)),
+        Constraint::Length(rows as u16 + 2),
+        Constraint::Fill(1),
+        Constraint::Length(u16::from(show_filter)),
+        Constraint::Length(1),
+    ])
+    .areas(area);
+    let summary_areas =
+        Layout::horizontal(vec![Constraint::Fill(1); panes.len()]).split(summary_area);
+
+    // Render each summary pane, keeping the last serialization error so the dashboard can show it instead of aborting.
+    // The live document uses the mapping kept on the state, anything else is mapped here.
+    // Diff mode marks changed fields in the single pane, the split panes already show what changed.
+    let mut errors = vec![];
+    let empty = JsonMap::new();
+    let changes = (!state.split).then_some(&state.changes);
+    for (((title, document), keys), area) in panes.iter().zip(&keys).zip(summary_areas.iter()) {
+        let lines = match document {
+            None => vec![Line::from("No document")],
+            Some(_) if !state.split && state.is_live() => summary_lines(
+                keys,
+                &state.redact_row(state.mapped_document.get(row).unwrap_or(&empty), row),
+                &state.column_types,
+                &state.aliases,
+                &state.theme,
+                changes,
+                &mut errors,
+            ),
+            Some(document) => {
+                let (map, types) = (map_row(document, row), map_column_types(document));
+                summary_lines(
+                    keys,
+                    &state.redact_row(&map, row),
+                    &types,
+                    &state.aliases,
+                    &state.theme,
+                    changes,
+                    &mut errors,
+                )
+            }
+        };
+
+        // Say which row is summarised when the document has several
+        let title = match document {
+            Some(document) if document.values.len() > 1 => {
+                format!("{title} - row {} of {}", row + 1, document.values.len())
+            }
+            _ => title.to_string(),
+        };
+        let summary = Paragraph::new(lines).block(bordered(state.ascii).title(title));
+        frame.render_widget(summary, *area);
+    }
+    let error = errors.pop();
+
+    // Render the filter, search query or highlight pattern, placing the cursor after it while it is being edited.
+    // A refused highlight pattern is followed by the reason.
+    if show_filter {
+        let query = match state.input_mode {
+            InputMode::Search if state.search.exact => {
+                format!("find (exact): {}", state.search.query)
+            }
+            InputMode::Search => format!("find: {}", state.search.query),
+            InputMode::Highlight => format!("highlight: {}", state.highlight_query),
+            InputMode::Palette => format!(":{}", state.palette_query),
+            _ => format!("/{}", state.filter),
+        };
+        if editing {
+            let width = query.chars().count() as u16;
+            frame.set_cursor_position((filter_area.x + width, filter_area.y));
+        }
+        let mut line = Line::from(query);
+        if let (InputMode::Highlight, Some(error)) = (state.input_mode, &state.highlight_error) {
+            line.push_span(Span::styled(format!("  {error}"), Style::new().red()));
+        }
+        frame.render_widget(Paragraph::new(line), filter_area);
+    }
+
+    render_recent_tabs(frame, tabs_area, state);
+    render_status_bar(frame, status_area, state, error.as_deref());
+
+    // Give the bottom half of the table area to a panel when one is open,
+    // preferring the search results, then the type warnings, then the histogram, then the inspector
+    let panel: Option<fn(&mut Frame, Rect, &AppState)> = if state.input_mode == InputMode::Palette {
+        Some(render_command_palette)
+    } else if state.input_mode == InputMode::Results {
+        Some(render_search_results)
+    } else if state.input_mode == InputMode::Columns {
+        Some(render_column_picker)
+    } else if state.warnings_panel {
+        Some(render_type_warnings)
+    } else if state.histogram {
+        Some(render_histogram)
+    } else if state.inspector {
+        Some(render_inspector)
+    } else {
+        None
+    };
+    match panel {
+        Some(render_panel) => {
+            let [table_area, panel_area] =
+                Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(table_area);
+            render_table(frame, table_area, state, view, config.max_column_width);
+            render_panel(frame, panel_area, state);
+        }
+        None => render_table(frame, table_area, state, view, config.max_column_width),
+    }
+}
+
+// Keys shown in a summary pane: the configured fields, or the document's own columns when the field order is AsReceived
+
+fn summary_keys(config: &Config, document: Option<&Log>) -> Vec<String> {
+    match (config.field_order, document) {
+        (FieldOrder::Fixed, _) => config.fields.clone(),
+        (FieldOrder::AsReceived, Some(document)) => document
+            .columns
+            .iter()
+            .map(|column| column.name.clone())
+            .collect(),
+        (FieldOrder::AsReceived, None) => vec![],
+    }
+}
+
+// Format the lines of a summary pane, substituting a placeholder for values that fail to serialize
+// and collecting the failures into errors. Keys are labelled with their alias when they have one,
+// and marked by how they differ from the previous document when changes are given.
+
+fn summary_lines(
+    keys: &[String],
+    map: &JsonMap,
+    types: &HashMap<String, String>,
+    aliases: &HashMap<String, String>,
+    theme: &Theme,
+    changes: Option<&HashMap<String, Change>>,
+    errors: &mut Vec<String>,
+) -> Vec<Line<'static>> {
+    keys.iter()
+        .flat_map(|item| {
+            let label = column_label(aliases, item);
+            let change = changes.and_then(|changes| changes.get(item).copied());
+            format_by_key(item, label, map, types, theme, change).unwrap_or_else(|e| {
+                errors.push(format!("failed to serialize {item}: {e}"));
+                vec![Line::from(format!("\"{label}\": {SERIALIZATION_ERROR}"))]
+            })
+        })
+        .collect()
+}
+
+// The render_recent_tabs function draws a tab for each of the most recent documents, newest first,
+// labelled with its number key and its @timestamp, or its position in history when it has none.
+// The tab of the document on screen is highlighted.
+
+fn render_recent_tabs(frame: &mut Frame, area: Rect, state: &AppState) {
+    let count = state.history_len().min(RECENT_TABS);
+    let titles = (0..count).filter_map(|tab| {
+        let log = state.get_historical(tab)?;
+        let label = match log_timestamp(log) {
+            Some(timestamp) => timestamp.format("%H:%M:%S").to_string(),
+            None => format!("#{}", state.history_len() - tab),
+        };
+        Some(format!("{} {label}", tab + 1))
+    });
+    let tabs = Tabs::new(titles)
+        .divider(if state.ascii {
+            "|"
+        } else {
+            ratatui::symbols::line::VERTICAL
+        })
+        .select(state.recent_tab())
+        .highlight_style(state.theme.selected_style());
+    frame.render_widget(tabs, area);
+}
+
+// The render_status_bar function draws the one-line status bar at the bottom of the screen.
+// It shows the channel on screen, how many type warnings the current document raised, the query latency
+// reported by Elasticsearch for the displayed document along with its minimum, average and maximum over
+// the session, how many documents have been received, the row count and the selected row, with a transient
+// message or the most recent error on the right. While an alert is raised the bar names the matched rule and flashes red.
+// Once anything has arrived it also says how long ago the latest document did, marked STALE in red
+// when that is longer than the staleness threshold.
+
+fn render_status_bar(frame: &mut Frame, area: Rect, state: &AppState, error: Option<&str>) {
+    let document = state.displayed_document();
+    let selection = match state.selected_row {
+        Some(index) => format!("row {}/{}", index + 1, document.values.len()),
+        None => String::from("no selection"),
+    };
+    let paused = if state.paused { " PAUSED |" } else { "" };
+    let diff = if state.diff { " DIFF |" } else { "" };
+    let expired = if state.is_expired() { " EXPIRED |" } else { "" };
+    let range = match state.time_range {
+        TimeRange::All => String::new(),
+        range => format!(" range: last {} |", range.label()),
+    };
+    let alert = match &state.alert {
+        Some(alert) => format!(
+            " ALERT {} = {} (a to acknowledge) |",
+            column_label(&state.aliases, &alert.rule.field),
+            alert.rule.equals
+        ),
+        None => String::new(),
+    };
+    let warnings = match state.type_warnings.len() {
+        0 => String::new(),
+        count => format!(" warnings: {count} (W) |"),
+    };
+    let polled = match state.polls.get(&state.channel) {
+        Some(PollStatus { error: Some(_), .. }) => String::from(", poll failed"),
+        Some(status) => format!(", polled {} ago", format_elapsed(status.at.elapsed())),
+        None => String::new(),
+    };
+    let channel = match state.channels.len() {
+        0 => format!(" channel: {}{polled} |", state.channel),
+        others => format!(" channel: {}{polled} (Tab: {others} more) |", state.channel),
+    };
+    let took = match state.counters.took_stats() {
+        Some(stats) => format!(
+            " (min {} avg {:.0} max {})",
+            stats.min, stats.average, stats.max
+        ),
+        None => String::new(),
+    };
+    let updated = match state.last_update.map(|at| at.elapsed()) {
+        Some(elapsed) if elapsed > state.stale_after => Span::styled(
+            format!(" STALE updated {} ago |", format_elapsed(elapsed)),
+            Style::new().red().bold(),
+        ),
+        Some(elapsed) => Span::raw(format!(" updated {} ago |", format_elapsed(elapsed))),
+        None => Span::raw(""),
+    };
+    let status = Line::from(vec![
+        Span::raw(format!(
+            "{alert}{paused}{expired}{diff}{range}{channel}{warnings}"
+        )),
+        updated,
+        Span::raw(format!(
+            " took: {}ms{took} | documents: {} | rows: {} | {selection}",
+            document.took,
+            state.counters.documents(),
+            document.values.len(),
+        )),
+    ]);
+
+    // Alternate between red and the normal colours every flash interval
+    let flash = state.alert.as_ref().is_some_and(|alert| {
+        (alert.raised.elapsed().as_millis() / ALERT_FLASH_INTERVAL.as_millis()).is_multiple_of(2)
+    });
+    let bar = state.theme.status_style(flash);
+    frame.render_widget(Paragraph::new(status).style(bar), area);
+    if let Some(error) = &state.server_error {
+        let error = Line::from(format!("{error} ")).red().bold().right_aligned();
+        frame.render_widget(Paragraph::new(error).style(bar), area);
+    } else if let Some(message) = state.active_status_message() {
+        let message = Line::from(format!("{message} ")).bold().right_aligned();
+        frame.render_widget(Paragraph::new(message).style(bar), area);
+    } else if let Some(error) = error.or(state.last_error.as_deref()) {
+        let error = Line::from(format!("{error} ")).red().right_aligned();
+        frame.render_widget(Paragraph::new(error).style(bar), area);
+    } else if let Some(error) = state
+        .polls
+        .get(&state.channel)
+        .and_then(|status| status.error.as_ref())
+    {
+        let error = Line::from(format!("poll failed: {error} "))
+            .red()
+            .right_aligned();
+        frame.render_widget(Paragraph::new(error).style(bar), area);
+    }
+}
+
+// Format an elapsed time compactly in its largest whole unit, as in "5s", "3m", "2h" or "1d"
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+// The render_feed function shows history as a log tail, one line per document built from the feed template
+// with the document's first row. The newest document is at the bottom and older ones scroll off the top,
+// unless the feed has been scrolled up, which the title shows as PAUSED-SCROLL rather than FOLLOW.
+// Only documents in the time range are listed.
+// The scroll is clamped here, where the number of lines that fit is known.
+
+fn render_feed(frame: &mut Frame, area: Rect, state: &AppState, template: &str) {
+    let height = usize::from(area.height.saturating_sub(2));
+    let in_range: Vec<&Log> = state
+        .history
+        .iter()
+        .filter(|log| state.in_time_range(log))
+        .collect();
+    let len = in_range.len();
+    let scroll = state.feed_scroll.min(len.saturating_sub(height));
+    let skip = len.saturating_sub(height + scroll);
+    let lines: Vec<Line> = in_range
+        .into_iter()
+        .skip(skip)
+        .take(height)
+        .map(|log| feed_line(template, log, &state.theme, &state.redact))
+        .collect();
+
+    let mode = if state.follow {
+        "FOLLOW"
+    } else {
+        "PAUSED-SCROLL"
+    };
+    let title = format!("Feed ({len} documents) {mode}");
+    let feed = Paragraph::new(lines).block(bordered(state.ascii).title(title));
+    frame.render_widget(feed, area);
+}
+
+// Fill in a feed template from the first row of a document. Each {field} placeholder becomes the field's
+// value styled by its type, or the mask when the field is redacted, fields the document lacks become a dimmed
+// <missing>, and text outside placeholders, including a '{' that is never closed, is kept as written.
+// Documents with several rows say how many more there are.
+
+fn feed_line(template: &str, log: &Log, theme: &Theme, redact: &HashSet<String>) -> Line<'static> {
+    let row = map_row(log, 0);
+    let types = map_column_types(log);
+    let mut spans = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        spans.push(Span::raw(rest[..start].to_string()));
+        let field = &rest[start + 1..end];
+        spans.push(match field_value(&row, field) {
+            Some(_) if redact.contains(field) => Span::raw(REDACTED),
+            Some(value) => {
+                let column_type = types.get(field).map_or("", String::as_str);
+                Span::styled(cell_text(value, column_type), theme.value_style(value))
+            }
+            None => Span::styled(MISSING, missing_style()),
+        });
+        rest = &rest[end + 1..];
+    }
+    spans.push(Span::raw(rest.to_string()));
+
+    if log.values.len() > 1 {
+        spans.push(Span::raw(format!(" (+{} more)", log.values.len() - 1)).dim());
     }
+    Line::from(spans)
 }
 
-// This function takes a key and a reference to a JSON map (JsonMap).
-// It attempts to retrieve the value associated with the given key from the map.
-// If the key exists in the map, it serializes the value to a pretty-printed JSON string.
-// The function then formats the key and the serialized value into a string and returns it.
-// If the key does not exist in the map, it returns a string indicating that the key is unknown.
+// The render_inspector function lists every column of the selected row with its value.
+// Nested objects and arrays are pretty-printed across several indented lines, and the pane
+// scrolls by the inspector offset kept on the application state.
 
-fn format_by_key(key: &str, map: &JsonMap) -> String {
-    match map.get(key) {
-        Some(value) => match serde_json::to_string_pretty(value) {
-            Ok(text) => format!("\"{key}\": {text}\n"),
-            Err(e) => panic!("error deserializing log: {:?}", e),
+fn render_inspector(frame: &mut Frame, area: Rect, state: &AppState) {
+    let document = state.displayed_document();
+    let row = state
+        .selected_row
+        .and_then(|index| Some((index, document.values.get(index)?)));
+    let redacted = JsonValue::from(REDACTED);
+
+    // With sizes on, each field is followed by the length of its value as JSON and the largest come first.
+    // In diff mode the columns of the previous document that this one lacks are listed after the rest.
+    let lines: Vec<Line> = match row {
+        Some((index, row)) => {
+            let mut fields: Vec<(&Column, &str, Option<&JsonValue>)> = document
+                .columns
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| !state.hidden_columns.contains(&column.name))
+                .map(|(i, column)| {
+                    let value = if state.is_redacted(&column.name, index) {
+                        Some(&redacted)
+                    } else {
+                        row.get(i)
+                    };
+                    (column, document.column_type(i), value)
+                })
+                .collect();
+            if state.sizes {
+                fields.sort_by_key(|(_, _, value)| cmp::Reverse(value.map_or(0, value_size)));
+            }
+            let mut lines: Vec<Line> = fields
+                .into_iter()
+                .flat_map(|(column, column_type, value)| {
+                    let change = state.changes.get(&column.name).copied();
+                    let mut lines = inspector_lines(
+                        column,
+                        column_type,
+                        &state.aliases,
+                        &state.theme,
+                        value,
+                        change,
+                    );
+                    if let (true, Some(value), Some(line)) = (state.sizes, value, lines.first_mut())
+                    {
+                        line.push_span(Span::raw(format!(" ({} B)", value_size(value))).dim());
+                    }
+                    lines
+                })
+                .collect();
+            let mut removed: Vec<&String> = state
+                .changes
+                .iter()
+                .filter(|(name, change)| {
+                    **change == Change::Removed
+                        && !document.columns.iter().any(|column| &column.name == *name)
+                })
+                .map(|(name, _)| name)
+                .collect();
+            removed.sort();
+            lines.extend(removed.into_iter().map(|name| {
+                Line::from(vec![
+                    format!("{}: ", column_label(&state.aliases, name)).bold(),
+                    Span::styled(MISSING, missing_style()),
+                    Span::styled(" (removed)", change_style()),
+                ])
+            }));
+            lines
+        }
+        None => vec![Line::from("No row selected")],
+    };
+
+    let title = match state.selected_row {
+        Some(index) => format!("Row {}", index + 1),
+        None => String::from("Row"),
+    };
+    let title = if state.sizes {
+        format!("{title} (by size)")
+    } else {
+        title
+    };
+    let mut inspector = Paragraph::new(lines)
+        .block(bordered(state.ascii).title(title))
+        .scroll((state.inspector_scroll, 0));
+    if state.wrap {
+        inspector = inspector.wrap(Wrap { trim: false });
+    }
+    frame.render_widget(inspector, area);
+}
+
+// The render_search_results function lists the rows found by the history search,
+// each with the history entry and row it came from and the matching value, highlighting the selected result.
+
+fn render_search_results(frame: &mut Frame, area: Rect, state: &AppState) {
+    let search = &state.search;
+    let total = state.history_len();
+    let items: Vec<ListItem> = search
+        .hits
+        .iter()
+        .map(|hit| {
+            ListItem::new(Line::from(vec![
+                format!(
+                    "history {} of {total}, row {}: ",
+                    hit.history_index + 1,
+                    hit.row + 1
+                )
+                .bold(),
+                Span::raw(hit.value.clone()),
+            ]))
+        })
+        .collect();
+
+    let title = format!(
+        "Search {} ({} found)",
+        column_label(&state.aliases, &search.field),
+        search.hits.len()
+    );
+    let block = bordered(state.ascii).title(title);
+    if items.is_empty() {
+        frame.render_widget(Paragraph::new("No matches").block(block), area);
+        return;
+    }
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(state.theme.selected_style());
+    let mut list_state = ListState::default().with_selected(Some(search.selected));
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+// The render_banner function draws the configured title centred in a bordered block, with {channel} replaced by the
+// channel on screen and {count} by the number of documents received since startup
+
+fn render_banner(frame: &mut Frame, area: Rect, state: &AppState, template: &str) {
+    let title = template
+        .replace("{channel}", &state.channel)
+        .replace("{count}", &state.counters.documents().to_string());
+    let banner = Paragraph::new(title.bold())
+        .centered()
+        .block(bordered(state.ascii));
+    frame.render_widget(banner, area);
+}
+
+// The render_command_palette function lists the actions matching the palette query, each with the keys it is
+// bound to and what it does, highlighting the one Enter would carry out.
+
+fn render_command_palette(frame: &mut Frame, area: Rect, state: &AppState) {
+    let entries = state.palette_entries();
+    let keys: Vec<String> = entries
+        .iter()
+        .map(|info| {
+            state
+                .action_keys
+                .get(&info.action)
+                .map_or_else(String::new, |keys| keys.join(" "))
+        })
+        .collect();
+    let name_width = entries
+        .iter()
+        .map(|info| info.action.name().len())
+        .max()
+        .unwrap_or_default();
+    let key_width = keys
+        .iter()
+        .map(|keys| keys.chars().count())
+        .max()
+        .unwrap_or_default();
+    let items: Vec<ListItem> = entries
+        .iter()
+        .zip(&keys)
+        .map(|(info, keys)| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:name_width$}  ", info.action.name())),
+                Span::raw(format!("{keys:key_width$}  ")).dim(),
+                Span::raw(info.description),
+            ]))
+        })
+        .collect();
+
+    let block = bordered(state.ascii).title("Commands (Enter runs, Esc closes)");
+    if items.is_empty() {
+        frame.render_widget(Paragraph::new("No matching commands").block(block), area);
+        return;
+    }
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(state.theme.selected_style());
+    let mut list_state = ListState::default().with_selected(Some(state.palette_selected));
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+// The render_column_picker function lists every column of the document on screen with a checkbox
+// showing whether it is shown, highlighting the one under the cursor.
+
+fn render_column_picker(frame: &mut Frame, area: Rect, state: &AppState) {
+    let document = state.displayed_document();
+    let items: Vec<ListItem> = document
+        .columns
+        .iter()
+        .map(|column| {
+            let check = if state.hidden_columns.contains(&column.name) {
+                "[ ]"
+            } else {
+                "[x]"
+            };
+            ListItem::new(format!(
+                "{check} {}",
+                column_label(&state.aliases, &column.name)
+            ))
+        })
+        .collect();
+
+    let block = bordered(state.ascii).title("Columns (Space toggles, Esc closes)");
+    if items.is_empty() {
+        frame.render_widget(Paragraph::new("No columns").block(block), area);
+        return;
+    }
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(state.theme.selected_style());
+    let mut list_state = ListState::default().with_selected(Some(state.column_picker));
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+// The render_type_warnings function lists the values of the current document that do not match their
+// column's declared type, one per line, so a broken pipeline is easy to spot.
+
+fn render_type_warnings(frame: &mut Frame, area: Rect, state: &AppState) {
+    let title = format!("Type warnings ({})", state.type_warnings.len());
+    let block = bordered(state.ascii).title(title);
+    if state.type_warnings.is_empty() {
+        let message = "every value matches its column type";
+        frame.render_widget(Paragraph::new(message).block(block), area);
+        return;
+    }
+    let lines: Vec<Line> = state
+        .type_warnings
+        .iter()
+        .map(|warning| Line::from(warning.as_str()).yellow())
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// The render_histogram function charts how the selected column's values are distributed over the visible rows.
+// The range between the smallest and largest value is split into equal buckets, each drawn as a bar labelled
+// with its lower bound. Columns that are not numeric, or hold no numbers, get a message instead of a chart.
+
+fn render_histogram(frame: &mut Frame, area: Rect, state: &AppState) {
+    let document = state.displayed_document();
+    let column = document.columns.get(state.selected_col);
+    let title = match column {
+        Some(column) => format!(
+            "Histogram {} ({} buckets)",
+            column_label(&state.aliases, &column.name),
+            state.histogram_buckets
+        ),
+        None => String::from("Histogram"),
+    };
+    let block = bordered(state.ascii).title(title);
+
+    let Some(column) =
+        column.filter(|_| NUMERIC_TYPES.contains(&document.column_type(state.selected_col)))
+    else {
+        frame.render_widget(Paragraph::new("not numeric").block(block), area);
+        return;
+    };
+    let values: Vec<f64> = state
+        .visible_rows()
+        .iter()
+        .filter_map(|&index| document.cell(index, state.selected_col))
+        .filter_map(TypedCell::number)
+        .collect();
+    if values.is_empty() {
+        let message = format!(
+            "no numeric values in {}",
+            column_label(&state.aliases, &column.name)
+        );
+        frame.render_widget(Paragraph::new(message).block(block), area);
+        return;
+    }
+
+    // Fit the bars to the width of the panel
+    let buckets = histogram(&values, state.histogram_buckets);
+    let inner_width = area.width.saturating_sub(2);
+    let bar_width = (inner_width / buckets.len() as u16)
+        .saturating_sub(1)
+        .max(1);
+    let bars: Vec<Bar> = buckets
+        .iter()
+        .map(|&(lower, count)| {
+            Bar::default()
+                .value(count)
+                .label(Line::from(((lower * 100.0).round() / 100.0).to_string()))
+        })
+        .collect();
+    let chart = BarChart::default()
+        .block(block)
+        .data(BarGroup::default().bars(&bars))
+        .bar_width(bar_width)
+        .bar_gap(1);
+    let chart = if state.ascii {
+        chart.bar_set(ASCII_BARS)
+    } else {
+        chart
+    };
+    frame.render_widget(chart, area);
+}
+
+// Length in bytes of a value written as compact JSON, as the inspector's sizes show it
+
+fn value_size(value: &JsonValue) -> usize {
+    serde_json::to_string(value).map_or(0, |json| json.len())
+}
+
+// Lines describing one field in the inspector: the column's label followed by its value,
+// with any further lines of a pretty-printed value indented underneath
+
+fn inspector_lines(
+    column: &Column,
+    column_type: &str,
+    aliases: &HashMap<String, String>,
+    theme: &Theme,
+    value: Option<&JsonValue>,
+    change: Option<Change>,
+) -> Vec<Line<'static>> {
+    let style = match (change, value) {
+        (Some(_), Some(_)) => change_style(),
+        (None, Some(value)) => theme.value_style(value),
+        (_, None) => missing_style(),
+    };
+    let text = match value {
+        Some(value @ (JsonValue::Object(_) | JsonValue::Array(_))) => {
+            serde_json::to_string_pretty(value).unwrap_or_else(|_| SERIALIZATION_ERROR.to_string())
+        }
+        Some(value) => cell_text(value, column_type),
+        None => MISSING.to_string(),
+    };
+
+    let mut parts = text.lines();
+    let first = parts.next().unwrap_or_default().to_string();
+    let mut lines = vec![Line::from(vec![
+        format!("{}: ", column_label(aliases, &column.name)).bold(),
+        Span::styled(first, style),
+    ])];
+    lines.extend(parts.map(|part| Line::styled(format!("  {part}"), style)));
+    if let (Some(line), Some(marker)) = (lines.last_mut(), change_marker(change)) {
+        line.push_span(Span::styled(marker, change_style()));
+    }
+    lines
+}
+
+// The render_table function draws the rows table into the given area.
+// Only the rows that pass the filter are shown, in sort order. Each column is as wide as its widest cell
+// up to max_width, with longer values cut short by an ellipsis, except that an expanded selected column may
+// take the whole width. Only as many columns as fit are shown starting at the horizontal scroll offset,
+// which is adjusted here to keep the selected column in view. The bottom border reports which columns are shown.
+
+fn render_table(
+    frame: &mut Frame,
+    table_area: Rect,
+    state: &AppState,
+    view: &mut View,
+    max_width: u16,
+) {
+    // Work out which rows pass the filter
+    let visible = state.visible_rows();
+    let total_rows = state.displayed_document().values.len();
+
+    // Title the table with the position in history when browsing and the match count when filtering
+    let mut title = match state.history_cursor {
+        None => String::from("Rows"),
+        Some(_)
+            if state.history_order != HistoryOrder::Received
+                || state.time_range != TimeRange::All =>
+        {
+            let sequence = state.history_sequence();
+            format!(
+                "Rows (history {} of {}, {})",
+                state.history_position(&sequence) + 1,
+                sequence.len(),
+                state.history_order.label()
+            )
+        }
+        Some(index) => format!("Rows (history {} of {})", index + 1, state.history_len()),
+    };
+    if !state.filter.is_empty() {
+        title.push_str(&format!(" [{} of {} match]", visible.len(), total_rows));
+    }
+    if let Some(pattern) = &state.highlight {
+        title.push_str(&format!(" [highlight: {pattern}]"));
+    }
+
+    // Sync the table selection with the selected row's position among the visible rows
+    let position = visible
+        .iter()
+        .position(|&row| Some(row) == state.selected_row);
+    view.table.select(position);
+
+    // Size every column to its header and widest visible cell, within the cap, giving hidden columns no width
+    let inner_width = usize::from(table_area.width.saturating_sub(2));
+    let document = state.displayed_document();
+    let columns_total = document.columns.len();
+    let widths: Vec<usize> = document
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            if state.hidden_columns.contains(&column.name) {
+                return 0;
+            }
+            let name = column_label(&state.aliases, &column.name);
+            let header = header_label(name, i, state.sort, state.ascii)
+                .chars()
+                .count();
+            let widest = visible
+                .iter()
+                .filter_map(|&index| document.cell(index, i))
+                .map(|cell| {
+                    cell.display(document.column_type(i), &state.numbers)
+                        .chars()
+                        .count()
+                })
+                .fold(header, usize::max);
+            let cap = if state.expanded && i == state.selected_col {
+                inner_width
+            } else {
+                usize::from(max_width).min(inner_width)
+            };
+            widest.min(cap).max(1)
+        })
+        .collect();
+
+    // Scroll horizontally so the selected column stays visible and the offset never leaves empty space.
+    // Hidden columns take up no room, not even for the gap after them.
+    let fits = |columns: &[usize]| {
+        columns
+            .iter()
+            .filter(|&&width| width > 0)
+            .map(|width| width + 1)
+            .sum::<usize>()
+            <= inner_width + 1
+    };
+    let mut offset = view.col_offset.min(state.selected_col);
+    while offset < state.selected_col && !fits(&widths[offset..=state.selected_col]) {
+        offset += 1;
+    }
+    let mut end = offset;
+    while end < columns_total && fits(&widths[offset..=end]) {
+        end += 1;
+    }
+    let end = end.max(offset + 1).min(columns_total);
+    while offset > 0 && end == columns_total && fits(&widths[offset - 1..end]) {
+        offset -= 1;
+    }
+    view.col_offset = offset;
+    let shown: Vec<usize> = (offset..end).filter(|&i| widths[i] > 0).collect();
+    let hidden = widths.iter().filter(|&&width| width == 0).count();
+    view.table
+        .select_column(shown.iter().position(|&i| i == state.selected_col));
+
+    // Build the header from the shown column labels, marking the sorted column, and one row per visible entry in values
+    let document = state.displayed_document();
+    let header = Row::new(shown.iter().map(|&i| {
+        truncate(
+            header_label(
+                column_label(&state.aliases, &document.columns[i].name),
+                i,
+                state.sort,
+                state.ascii,
+            ),
+            widths[i],
+            state.ascii,
+        )
+    }))
+    .style(state.theme.header_style());
+    let rows = visible.iter().map(|&index| {
+        Row::new(shown.iter().map(|&i| match document.cell(index, i) {
+            Some(_) if state.is_redacted(&document.columns[i].name, index) => {
+                Cell::from(truncate(REDACTED.to_string(), widths[i], state.ascii))
+            }
+            Some(cell) => format_cell(cell, document, i, widths[i], state),
+            None => Cell::from(Text::styled(
+                truncate(MISSING.to_string(), widths[i], state.ascii),
+                missing_style(),
+            )),
+        }))
+    });
+    let widths = shown.iter().map(|&i| Constraint::Length(widths[i] as u16));
+
+    // Report which columns are on screen and how many are hidden
+    let mut block = bordered(state.ascii).title(title);
+    if columns_total > 0 {
+        let hidden = match hidden {
+            0 => String::new(),
+            hidden => format!(", {hidden} hidden"),
+        };
+        block = block.title_bottom(format!(
+            "cols {}-{end} of {columns_total}{hidden}",
+            offset + 1
+        ));
+    }
+
+    // Create and render the table widget
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .row_highlight_style(state.theme.selected_style())
+        .column_highlight_style(Style::new().underlined());
+    frame.render_stateful_widget(table, table_area, &mut view.table);
+    view.area = table_area;
+}
+
+// Header text for a column, with an arrow marking the sort direction when the table is sorted by it,
+// or ^ and v in ASCII mode
+
+fn header_label(
+    name: &str,
+    index: usize,
+    sort: Option<(usize, SortDirection)>,
+    ascii: bool,
+) -> String {
+    let arrow = match sort {
+        Some((column, direction)) if column == index => match (direction, ascii) {
+            (SortDirection::Ascending, false) => "▲",
+            (SortDirection::Descending, false) => "▼",
+            (SortDirection::Ascending, true) => "^",
+            (SortDirection::Descending, true) => "v",
         },
-        None => format!("\"{key}\": unknown\n"),
+        _ => return name.to_string(),
+    };
+    format!("{name} {arrow}")
+}
+
+// A block with a border all round, drawn with plain ASCII characters in ASCII mode
+
+fn bordered(ascii: bool) -> Block<'static> {
+    let block = Block::bordered();
+    if ascii {
+        block.border_set(ASCII_BORDER)
+    } else {
+        block
+    }
+}
+
+// This function takes a key, the label to show it under, a reference to a JSON map (JsonMap)
+// and the column types of the document.
+// It attempts to retrieve the value associated with the given key from the map, following a dotted key
+// into nested objects and arrays when no column has that exact name.
+// If the key's column type has a dedicated display format (dates, booleans), that is used,
+// otherwise it serializes the value to a pretty-printed JSON string.
+// The function then formats the label and the serialized value into styled lines and returns them,
+// with the label in the default colour and the value coloured by its JSON type.
+// If the key does not exist in the map, it returns a line marking the key as missing, which is styled apart
+// from a key that is present with a null value.
+// In diff mode a value that differs from the previous document is coloured apart instead,
+// and fields new or gone since then say so after the value.
+// Serialization failures are returned to the caller so the draw loop can keep running.
+
+fn format_by_key(
+    key: &str,
+    label: &str,
+    map: &JsonMap,
+    types: &HashMap<String, String>,
+    theme: &Theme,
+    change: Option<Change>,
+) -> Result<Vec<Line<'static>>, serde_json::Error> {
+    let label = Span::raw(format!("\"{label}\": "));
+    match field_value(map, key) {
+        Some(value) => {
+            let column_type = types.get(key).map_or("", String::as_str);
+            let text = match format_typed(value, column_type) {
+                Some(text) => text,
+                None => serde_json::to_string_pretty(value)?,
+            };
+
+            // Pretty-printed objects and arrays carry on over several lines in the same colour
+            let style = change.map_or_else(|| theme.value_style(value), |_| change_style());
+            let mut parts = text.lines();
+            let first = parts.next().unwrap_or_default().to_string();
+            let mut lines = vec![Line::from(vec![label, Span::styled(first, style)])];
+            lines.extend(parts.map(|part| Line::styled(part.to_string(), style)));
+            if let (Some(line), Some(marker)) = (lines.last_mut(), change_marker(change)) {
+                line.push_span(Span::styled(marker, change_style()));
+            }
+            Ok(lines)
+        }
+        None => {
+            let mut line = Line::from(vec![label, Span::styled(MISSING, missing_style())]);
+            if let Some(marker) = change_marker(change) {
+                line.push_span(Span::styled(marker, change_style()));
+            }
+            Ok(vec![line])
+        }
+    }
+}
+
+// Colour of a value that differs from the previous document in diff mode
+
+fn change_style() -> Style {
+    Style::new().yellow()
+}
+
+// Style of a table cell matching the highlight pattern
+
+fn highlight_style() -> Style {
+    Style::new().black().on_yellow()
+}
+
+// Note shown after a field that is new or gone since the previous document, None for a changed or unchanged one
+
+fn change_marker(change: Option<Change>) -> Option<&'static str> {
+    match change? {
+        Change::Added => Some(" (new)"),
+        Change::Removed => Some(" (removed)"),
+        Change::Changed => None,
+    }
+}
+
+// Style of the placeholder for a field the document does not have at all, dimmed so it reads apart from a null
+
+fn missing_style() -> Style {
+    Style::new().dim()
+}
+
+// The format_cell function turns a single typed value into the table cell for the given column of its document.
+// The text is that of TypedCell::display in the state's number format, truncated to the column width and
+// coloured by the value's type, or emphasised when the untruncated text matches the highlight pattern,
+// and numeric columns are right-aligned.
+// A boolean in a column with a symbol is drawn as that symbol in its own colour instead.
+
+fn format_cell(
+    cell: &TypedCell,
+    document: &Log,
+    column: usize,
+    width: usize,
+    state: &AppState,
+) -> Cell<'static> {
+    let column_type = document.column_type(column);
+    let mut text = cell.display(column_type, &state.numbers);
+    let highlighted = state
+        .highlight
+        .as_ref()
+        .is_some_and(|pattern| pattern.is_match(&text));
+    let mut style = state.theme.cell_style(cell);
+    if let (Some(symbol), TypedCell::Bool(flag)) =
+        (state.symbol_for(&document.columns[column]), cell)
+    {
+        let (shown, color) = symbol.render(*flag, state.ascii);
+        (text, style) = (shown.to_string(), color);
+    }
+    if highlighted {
+        style = highlight_style();
+    }
+    let text = Text::styled(truncate(text, width, state.ascii), style);
+
+    if NUMERIC_TYPES.contains(&column_type) {
+        Cell::from(text.right_aligned())
+    } else {
+        Cell::from(text)
     }
 }
@@ -0,0 +1,152 @@
+use rs_es_dashview::{
+    routes, AppState, JsonValue, SharedAppState, HISTORY_CAPACITY, MAX_BODY_BYTES,
+};
+use serde_json::json;
+use warp::http::StatusCode;
+
+// POST a body to the given path of a fresh set of routes over the state, returning the status and JSON reply
+
+async fn post(state: &SharedAppState, path: &str, body: &JsonValue) -> (StatusCode, JsonValue) {
+    let routes = routes(state.clone(), None, MAX_BODY_BYTES, None);
+    let response = warp::test::request()
+        .method("POST")
+        .path(path)
+        .json(body)
+        .reply(&routes)
+        .await;
+    let reply = serde_json::from_slice(response.body()).unwrap_or(JsonValue::Null);
+    (response.status(), reply)
+}
+
+#[tokio::test]
+async fn posted_document_becomes_the_current_document() {
+    let state = AppState::new(HISTORY_CAPACITY);
+    let document = json!({
+        "values": [["web-1", "alice"]],
+        "took": 12,
+        "columns": [
+            {"name": "host.name", "type": "keyword"},
+            {"name": "user.name", "type": "keyword"}
+        ]
+    });
+
+    let (status, reply) = post(&state, "/data", &document).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply, document);
+
+    let state = state.read().unwrap();
+    assert_eq!(
+        serde_json::to_value(&state.current_document).unwrap(),
+        document
+    );
+    assert_eq!(state.mapped_document.len(), 1);
+    assert_eq!(state.mapped_document[0]["host.name"], json!("web-1"));
+    assert_eq!(state.mapped_document[0]["user.name"], json!("alice"));
+    assert_eq!(state.column_types["host.name"], "keyword");
+}
+
+#[tokio::test]
+async fn every_row_of_a_document_is_mapped_in_order() {
+    let state = AppState::new(HISTORY_CAPACITY);
+    let document = json!({
+        "values": [["web-1", 200], ["web-2", 404], ["web-3", 500]],
+        "took": 3,
+        "columns": [
+            {"name": "host.name", "type": "keyword"},
+            {"name": "status", "type": "long"}
+        ]
+    });
+
+    let (status, reply) = post(&state, "/data", &document).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply, document);
+
+    let state = state.read().unwrap();
+    let hosts: Vec<_> = state
+        .mapped_document
+        .iter()
+        .map(|row| (row["host.name"].clone(), row["status"].clone()))
+        .collect();
+    assert_eq!(
+        hosts,
+        vec![
+            (json!("web-1"), json!(200)),
+            (json!("web-2"), json!(404)),
+            (json!("web-3"), json!(500)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn document_without_values_leaves_no_rows() {
+    let state = AppState::new(HISTORY_CAPACITY);
+    let document = json!({
+        "values": [],
+        "took": 0,
+        "columns": [{"name": "host.name", "type": "keyword"}]
+    });
+
+    let (status, reply) = post(&state, "/data", &document).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(reply, document);
+
+    let state = state.read().unwrap();
+    assert!(state.current_document.values.is_empty());
+    assert_eq!(state.current_document.columns.len(), 1);
+    assert!(state.mapped_document.is_empty());
+    assert_eq!(state.selected_row, None);
+}
+
+#[tokio::test]
+async fn short_rows_map_only_the_columns_they_have() {
+    let state = AppState::new(HISTORY_CAPACITY);
+    let document = json!({
+        "values": [["web-1"], ["web-2", "bob"]],
+        "columns": [
+            {"name": "host.name", "type": "keyword"},
+            {"name": "user.name", "type": "keyword"}
+        ]
+    });
+
+    let (status, _) = post(&state, "/data", &document).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let state = state.read().unwrap();
+    assert!(!state.mapped_document[0].contains_key("user.name"));
+    assert_eq!(state.mapped_document[1]["user.name"], json!("bob"));
+}
+
+#[tokio::test]
+async fn mapped_format_replies_with_one_object_per_row() {
+    let state = AppState::new(HISTORY_CAPACITY);
+    let document = json!({
+        "values": [["web-1", 200], ["web-2", 404]],
+        "columns": [
+            {"name": "host.name", "type": "keyword"},
+            {"name": "status", "type": "long"}
+        ]
+    });
+
+    let (status, reply) = post(&state, "/data?format=mapped", &document).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        reply,
+        json!([
+            {"host.name": "web-1", "status": 200},
+            {"host.name": "web-2", "status": 404}
+        ])
+    );
+}
+
+#[tokio::test]
+async fn malformed_document_is_rejected_and_state_is_untouched() {
+    let state = AppState::new(HISTORY_CAPACITY);
+
+    let (status, reply) = post(&state, "/data", &json!({"columns": []})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(reply["request_id"].is_string());
+
+    let state = state.read().unwrap();
+    assert!(!state.has_data);
+    assert!(state.history.is_empty());
+}